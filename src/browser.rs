@@ -0,0 +1,244 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use ratatui::widgets::ListState;
+
+use crate::Track;
+
+/// Artist → Album → Track tree of the library
+pub struct LibraryTree {
+    pub artists: Vec<TreeArtist>,
+    pub state: ListState,
+    expanded_artists: HashSet<usize>,
+    expanded_albums: HashSet<(usize, usize)>,
+}
+
+pub struct TreeArtist {
+    pub name: String,
+    pub albums: Vec<TreeAlbum>,
+}
+
+pub struct TreeAlbum {
+    pub name: String,
+    pub year: Option<String>,
+    /// Indices into the library, in disc and track order
+    pub tracks: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TreeRow {
+    Artist(usize),
+    Album(usize, usize),
+    Track(usize, usize, usize),
+}
+
+impl LibraryTree {
+    pub fn new(library: &[Track]) -> Self {
+        let mut artists: Vec<TreeArtist> = Vec::new();
+        let mut artist_indices: HashMap<String, usize> = HashMap::new();
+        let mut album_indices: HashMap<(usize, String), usize> = HashMap::new();
+
+        for (i, track) in library.iter().enumerate() {
+            let metadata = &track.metadata;
+
+            // Compilations are grouped under their album artist
+            let artist_name = metadata
+                .album_artist
+                .clone()
+                .or_else(|| metadata.artist.clone())
+                .unwrap_or_else(|| "Unknown Artist".to_string());
+            let album_name = metadata
+                .album
+                .clone()
+                .unwrap_or_else(|| "Unknown Album".to_string());
+
+            let a = *artist_indices
+                .entry(artist_name.to_lowercase())
+                .or_insert_with(|| {
+                    artists.push(TreeArtist {
+                        name: artist_name,
+                        albums: Vec::new(),
+                    });
+
+                    return artists.len() - 1;
+                });
+
+            let albums = &mut artists[a].albums;
+            let b = *album_indices
+                .entry((a, album_name.to_lowercase()))
+                .or_insert_with(|| {
+                    albums.push(TreeAlbum {
+                        name: album_name,
+                        year: metadata.year.clone(),
+                        tracks: Vec::new(),
+                    });
+
+                    return albums.len() - 1;
+                });
+
+            albums[b].tracks.push(i);
+        }
+
+        artists.sort_by_key(|a| a.name.to_lowercase());
+
+        for artist in &mut artists {
+            artist.albums.sort_by_key(|a| (a.year.is_none(), a.year.clone(), a.name.to_lowercase()));
+
+            for album in &mut artist.albums {
+                album.tracks.sort_by_key(|&i| {
+                    let metadata = &library[i].metadata;
+
+                    return (
+                        metadata.disc_number.unwrap_or(0),
+                        metadata.track_number.unwrap_or(u32::MAX),
+                        library[i].start,
+                        metadata.title.clone().unwrap_or_default().to_lowercase(),
+                    );
+                });
+            }
+        }
+
+        return Self {
+            artists,
+            state: ListState::default().with_selected(Some(0)),
+            expanded_artists: HashSet::new(),
+            expanded_albums: HashSet::new(),
+        };
+    }
+
+    pub fn rows(&self) -> Vec<TreeRow> {
+        let mut rows = Vec::new();
+
+        for (a, artist) in self.artists.iter().enumerate() {
+            rows.push(TreeRow::Artist(a));
+
+            if !self.expanded_artists.contains(&a) {
+                continue;
+            }
+
+            for (b, album) in artist.albums.iter().enumerate() {
+                rows.push(TreeRow::Album(a, b));
+
+                if !self.expanded_albums.contains(&(a, b)) {
+                    continue;
+                }
+
+                for t in 0..album.tracks.len() {
+                    rows.push(TreeRow::Track(a, b, t));
+                }
+            }
+        }
+
+        return rows;
+    }
+
+    pub fn is_expanded(&self, row: TreeRow) -> bool {
+        match row {
+            TreeRow::Artist(a) => {
+                return self.expanded_artists.contains(&a);
+            },
+            TreeRow::Album(a, b) => {
+                return self.expanded_albums.contains(&(a, b));
+            },
+            TreeRow::Track(..) => {
+                return false;
+            },
+        }
+    }
+
+    pub fn selected_row(&self) -> Option<TreeRow> {
+        return self
+            .rows()
+            .get(self.state.selected()?)
+            .copied();
+    }
+
+    pub fn move_selection(&mut self, offset: i32) {
+        let len = self.rows().len() as i32;
+
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.state.selected().unwrap_or(0) as i32;
+
+        self.state.select(Some((selected + offset).clamp(0, len - 1) as usize));
+    }
+
+    pub fn expand(&mut self) {
+        match self.selected_row() {
+            Some(TreeRow::Artist(a)) => {
+                self.expanded_artists.insert(a);
+            },
+            Some(TreeRow::Album(a, b)) => {
+                self.expanded_albums.insert((a, b));
+            },
+            _ => {},
+        }
+    }
+
+    /// Collapses the selected node, or jumps to its parent if already collapsed
+    pub fn collapse(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+
+        let parent = match row {
+            TreeRow::Artist(a) => {
+                self.expanded_artists.remove(&a);
+
+                None
+            },
+            TreeRow::Album(a, b) => {
+                if self.expanded_albums.remove(&(a, b)) {
+                    None
+                } else {
+                    Some(TreeRow::Artist(a))
+                }
+            },
+            TreeRow::Track(a, b, _) => {
+                Some(TreeRow::Album(a, b))
+            },
+        };
+
+        if let Some(parent) = parent {
+            self.select_row(parent);
+        }
+    }
+
+    pub fn select_row(&mut self, row: TreeRow) {
+        if let Some(index) = self.rows().iter().position(|r| *r == row) {
+            self.state.select(Some(index));
+        }
+    }
+
+    /// Library indices of everything under the row, and the position to start playing from
+    pub fn row_tracks(&self, row: TreeRow) -> (Vec<usize>, usize) {
+        match row {
+            TreeRow::Artist(a) => {
+                let tracks = self.artists[a]
+                    .albums
+                    .iter()
+                    .flat_map(|album| album.tracks.iter().copied())
+                    .collect();
+
+                return (tracks, 0);
+            },
+            TreeRow::Album(a, b) => {
+                return (self.artists[a].albums[b].tracks.clone(), 0);
+            },
+            TreeRow::Track(a, b, t) => {
+                return (self.artists[a].albums[b].tracks.clone(), t);
+            },
+        }
+    }
+}
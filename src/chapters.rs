@@ -0,0 +1,587 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    fs::File,
+    io::{
+        Read,
+        Seek,
+        SeekFrom,
+    },
+    path::Path,
+    time::Duration,
+};
+
+/// Far more than any real chapter track has, anything bigger is a broken file
+const MAX_CHAPTER_SAMPLES: usize = 65536;
+
+#[derive(Clone)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+pub fn read_chapters(path: &Path) -> Vec<Chapter> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    let chapters = match extension.as_str() {
+        "mp3" => read_id3_chapters(path),
+        "m4a" | "m4b" | "mp4" => read_mp4_chapters(path),
+        _ => None,
+    };
+
+    let mut chapters = chapters.unwrap_or_default();
+
+    chapters.sort_by_key(|c| c.start);
+
+    return chapters;
+}
+
+/// Index of the chapter playing at `position`
+pub fn current_chapter(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    return chapters
+        .iter()
+        .rposition(|c| c.start <= position);
+}
+
+/// The ID3v2 tag at the start of the file, without its extended header, and its version
+pub fn read_id3_tag(path: &Path) -> Option<(Vec<u8>, u8)> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 10];
+
+    file.read_exact(&mut header).ok()?;
+
+    if &header[..3] != b"ID3" {
+        return None;
+    }
+
+    let version = header[3];
+    let tag_size = synchsafe(&header[6..10]) as usize;
+    let mut tag = vec![0; tag_size];
+
+    file.read_exact(&mut tag).ok()?;
+
+    // Skip the extended header if present
+    let mut position = 0;
+
+    if header[5] & 0x40 != 0 && tag.len() >= 4 {
+        position = if version == 4 {
+            synchsafe(&tag[..4]) as usize
+        } else {
+            u32::from_be_bytes(tag[..4].try_into().ok()?) as usize + 4
+        };
+    }
+
+    tag.drain(..position.min(tag.len()));
+
+    return Some((tag, version));
+}
+
+fn read_id3_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let (tag, version) = read_id3_tag(path)?;
+
+    let mut chapters = Vec::new();
+
+    for (id, body) in id3_frames(&tag, version) {
+        if &id != b"CHAP" {
+            continue;
+        }
+
+        let Some(id_end) = body.iter().position(|&b| b == 0) else {
+            continue;
+        };
+
+        let times = &body[id_end + 1..];
+
+        if times.len() < 16 {
+            continue;
+        }
+
+        let start = u32::from_be_bytes(times[..4].try_into().ok()?);
+        let title = id3_frames(&times[16..], version)
+            .into_iter()
+            .find(|(id, _)| id == b"TIT2")
+            .map(|(_, body)| decode_id3_text(body))
+            .unwrap_or_else(|| String::from_utf8_lossy(&body[..id_end]).to_string());
+
+        chapters.push(Chapter {
+            start: Duration::from_millis(start as u64),
+            title,
+        });
+    }
+
+    return Some(chapters);
+}
+
+pub fn id3_frames(data: &[u8], version: u8) -> Vec<([u8; 4], &[u8])> {
+    let mut frames = Vec::new();
+    let mut position = 0;
+
+    while position + 10 <= data.len() {
+        let header = &data[position..position + 10];
+
+        if header[0] == 0 {
+            break;
+        }
+
+        let id: [u8; 4] = header[..4].try_into().unwrap_or_default();
+        let size = if version == 4 {
+            synchsafe(&header[4..8])
+        } else {
+            u32::from_be_bytes(header[4..8].try_into().unwrap_or_default())
+        } as usize;
+
+        let start = position + 10;
+        let end = (start + size).min(data.len());
+
+        frames.push((id, &data[start..end]));
+        position = end;
+    }
+
+    return frames;
+}
+
+fn decode_id3_text(body: &[u8]) -> String {
+    let Some((&encoding, text)) = body.split_first() else {
+        return String::new();
+    };
+
+    let text = match encoding {
+        0 => {
+            text
+                .iter()
+                .map(|&b| b as char)
+                .collect()
+        },
+        1 | 2 => {
+            let big_endian = encoding == 2 || text.starts_with(&[0xfe, 0xff]);
+            let text = text
+                .strip_prefix(&[0xfe, 0xff])
+                .or_else(|| text.strip_prefix(&[0xff, 0xfe]))
+                .unwrap_or(text);
+
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        return u16::from_be_bytes([pair[0], pair[1]]);
+                    } else {
+                        return u16::from_le_bytes([pair[0], pair[1]]);
+                    }
+                })
+                .collect();
+
+            String::from_utf16_lossy(&units)
+        },
+        _ => {
+            String::from_utf8_lossy(text).to_string()
+        },
+    };
+
+    return text
+        .trim_end_matches('\0')
+        .to_string();
+}
+
+fn synchsafe(bytes: &[u8]) -> u32 {
+    return bytes
+        .iter()
+        .fold(0, |size, &b| (size << 7) | (b & 0x7f) as u32);
+}
+
+fn read_mp4_chapters(path: &Path) -> Option<Vec<Chapter>> {
+    let mut file = File::open(path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+    let mut position = 0;
+
+    // `moov` may be at the end of large files, so walk the top level boxes by seeking
+    let moov = loop {
+        if position + 8 > file_size {
+            return None;
+        }
+
+        let mut header = [0; 8];
+
+        file.seek(SeekFrom::Start(position)).ok()?;
+        file.read_exact(&mut header).ok()?;
+
+        let mut size = u32::from_be_bytes(header[..4].try_into().ok()?) as u64;
+        let mut header_size = 8;
+
+        if size == 1 {
+            let mut large_size = [0; 8];
+
+            file.read_exact(&mut large_size).ok()?;
+            size = u64::from_be_bytes(large_size);
+            header_size = 16;
+        } else if size == 0 {
+            size = file_size - position;
+        }
+
+        if size < header_size {
+            return None;
+        }
+
+        if &header[4..8] == b"moov" {
+            if size > 64 * 1024 * 1024 {
+                return None;
+            }
+
+            let mut moov = vec![0; (size - header_size) as usize];
+
+            file.read_exact(&mut moov).ok()?;
+
+            break moov;
+        }
+
+        position += size;
+    };
+
+    if let Some(chapters) = read_chpl_chapters(&moov).filter(|c| !c.is_empty()) {
+        return Some(chapters);
+    }
+
+    return read_chapter_track(&moov, &mut file);
+}
+
+/// Nero style chapters stored in `moov/udta/chpl`
+fn read_chpl_chapters(moov: &[u8]) -> Option<Vec<Chapter>> {
+    let chpl = find_box(moov, &["udta", "chpl"])?;
+    let version = *chpl.first()?;
+    let mut position = if version > 0 { 8 } else { 4 };
+    let count = *chpl.get(position)?;
+    let mut chapters = Vec::new();
+
+    position += 1;
+
+    for _ in 0..count {
+        let start = u64::from_be_bytes(chpl.get(position..position + 8)?.try_into().ok()?);
+        let length = *chpl.get(position + 8)? as usize;
+        let title = chpl.get(position + 9..position + 9 + length)?;
+
+        chapters.push(Chapter {
+            // 100 nanosecond units
+            start: Duration::from_nanos(start * 100),
+            title: String::from_utf8_lossy(title).to_string(),
+        });
+
+        position += 9 + length;
+    }
+
+    return Some(chapters);
+}
+
+/// QuickTime style chapters, a text track referenced by the audio track's `tref/chap`
+fn read_chapter_track(moov: &[u8], file: &mut File) -> Option<Vec<Chapter>> {
+    let tracks: Vec<&[u8]> = child_boxes(moov)
+        .into_iter()
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, body)| body)
+        .collect();
+
+    let chapter_track_id = tracks
+        .iter()
+        .find_map(|trak| {
+            let chap = find_box(trak, &["tref", "chap"])?;
+
+            return Some(u32::from_be_bytes(chap.get(..4)?.try_into().ok()?));
+        })?;
+
+    let trak = tracks
+        .into_iter()
+        .find(|trak| track_id(trak) == Some(chapter_track_id))?;
+
+    let mdhd = find_box(trak, &["mdia", "mdhd"])?;
+    let timescale = if mdhd.first() == Some(&1) {
+        read_u32(mdhd, 20)?
+    } else {
+        read_u32(mdhd, 12)?
+    };
+
+    if timescale == 0 {
+        return None;
+    }
+
+    let stbl = find_box(trak, &["mdia", "minf", "stbl"])?;
+
+    // Sample durations, as runs of samples with the same one
+    let stts = find_box(stbl, &["stts"])?;
+    let duration_runs: Vec<(u32, u64)> = (0..table_length(stts, 4, 8)?)
+        .map(|i| Some((read_u32(stts, 8 + i * 8)?, read_u32(stts, 12 + i * 8)? as u64)))
+        .collect::<Option<Vec<(u32, u64)>>>()?;
+    let mut durations = duration_runs
+        .iter()
+        .flat_map(|&(count, delta)| std::iter::repeat_n(delta, count as usize));
+
+    // Sample sizes, the counts come from the file so they're checked before anything is allocated
+    let stsz = find_box(stbl, &["stsz"])?;
+    let sample_size = read_u32(stsz, 4)?;
+    let sample_count = read_u32(stsz, 8)? as usize;
+
+    if sample_count > MAX_CHAPTER_SAMPLES {
+        return None;
+    }
+
+    let sizes: Vec<u32> = if sample_size != 0 {
+        vec![sample_size; sample_count]
+    } else {
+        if table_length(stsz, 8, 4)? < sample_count {
+            return None;
+        }
+
+        (0..sample_count)
+            .map(|i| read_u32(stsz, 12 + i * 4))
+            .collect::<Option<Vec<u32>>>()?
+    };
+
+    // Chunk offsets
+    let chunk_offsets: Vec<u64> = if let Some(stco) = find_box(stbl, &["stco"]) {
+        (0..table_length(stco, 4, 4)?)
+            .map(|i| read_u32(stco, 8 + i * 4).map(|o| o as u64))
+            .collect::<Option<Vec<u64>>>()?
+    } else {
+        let co64 = find_box(stbl, &["co64"])?;
+
+        (0..table_length(co64, 4, 8)?)
+            .map(|i| {
+                let bytes = co64.get(8 + i * 8..16 + i * 8)?;
+
+                return Some(u64::from_be_bytes(bytes.try_into().ok()?));
+            })
+            .collect::<Option<Vec<u64>>>()?
+    };
+
+    // Samples per chunk, as runs starting at a given chunk
+    let stsc = find_box(stbl, &["stsc"])?;
+    let runs: Vec<(usize, usize)> = (0..table_length(stsc, 4, 12)?)
+        .map(|i| {
+            let first_chunk = read_u32(stsc, 8 + i * 12)? as usize;
+            let samples = read_u32(stsc, 12 + i * 12)? as usize;
+
+            return Some((first_chunk, samples));
+        })
+        .collect::<Option<Vec<(usize, usize)>>>()?;
+
+    let mut sample_offsets = Vec::with_capacity(sample_count);
+
+    for (chunk, chunk_offset) in chunk_offsets.iter().enumerate() {
+        let samples_in_chunk = runs
+            .iter()
+            .rev()
+            .find(|(first_chunk, _)| *first_chunk <= chunk + 1)
+            .map(|(_, samples)| *samples)
+            .unwrap_or(1);
+
+        let mut offset = *chunk_offset;
+
+        for _ in 0..samples_in_chunk {
+            let Some(size) = sizes.get(sample_offsets.len()) else {
+                break;
+            };
+
+            sample_offsets.push(offset);
+            offset += *size as u64;
+        }
+    }
+
+    let mut chapters = Vec::new();
+    let mut time = 0;
+
+    for (i, offset) in sample_offsets.iter().enumerate() {
+        // Chapter titles are short, anything bigger is not a text sample
+        let size = (sizes[i] as usize).min(u16::MAX as usize + 2);
+        let mut sample = vec![0; size];
+
+        file.seek(SeekFrom::Start(*offset)).ok()?;
+        file.read_exact(&mut sample).ok()?;
+
+        let length = sample
+            .get(..2)
+            .map(|l| u16::from_be_bytes([l[0], l[1]]) as usize)
+            .unwrap_or(0);
+        let text = sample
+            .get(2..2 + length)
+            .unwrap_or(&[]);
+
+        let title = if text.starts_with(&[0xfe, 0xff]) || text.starts_with(&[0xff, 0xfe]) {
+            let mut with_encoding = vec![1];
+
+            with_encoding.extend_from_slice(text);
+
+            decode_id3_text(&with_encoding)
+        } else {
+            String::from_utf8_lossy(text).to_string()
+        };
+
+        chapters.push(Chapter {
+            start: Duration::from_secs_f64(time as f64 / timescale as f64),
+            title,
+        });
+
+        time += durations.next().unwrap_or(0);
+    }
+
+    return Some(chapters);
+}
+
+fn track_id(trak: &[u8]) -> Option<u32> {
+    let tkhd = find_box(trak, &["tkhd"])?;
+
+    if tkhd.first() == Some(&1) {
+        return read_u32(tkhd, 20);
+    } else {
+        return read_u32(tkhd, 12);
+    }
+}
+
+fn find_box<'a>(data: &'a [u8], path: &[&str]) -> Option<&'a [u8]> {
+    let (name, rest) = path.split_first()?;
+
+    let body = child_boxes(data)
+        .into_iter()
+        .find(|(box_name, _)| box_name == name.as_bytes())
+        .map(|(_, body)| body)?;
+
+    if rest.is_empty() {
+        return Some(body);
+    } else {
+        return find_box(body, rest);
+    }
+}
+
+fn child_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut position = 0;
+
+    while position + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[position..position + 4].try_into().unwrap_or_default()) as usize;
+        let name: [u8; 4] = data[position + 4..position + 8].try_into().unwrap_or_default();
+
+        let (start, end) = match size {
+            0 => {
+                (position + 8, data.len())
+            },
+            1 => {
+                let Some(large_size) = data.get(position + 8..position + 16) else {
+                    break;
+                };
+
+                let large_size = u64::from_be_bytes(large_size.try_into().unwrap_or_default()) as usize;
+
+                (position + 16, position.saturating_add(large_size))
+            },
+            _ => {
+                (position + 8, position + size)
+            },
+        };
+
+        if end < start || end > data.len() {
+            break;
+        }
+
+        boxes.push((name, &data[start..end]));
+        position = end;
+    }
+
+    return boxes;
+}
+
+/// Entry count of a sample table stored at `offset`, when that many entries fit in the rest of the box
+fn table_length(data: &[u8], offset: usize, entry_size: usize) -> Option<usize> {
+    let count = read_u32(data, offset)? as usize;
+    let room = data.len().saturating_sub(offset + 4) / entry_size;
+
+    if count > room {
+        return None;
+    }
+
+    return Some(count);
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    return Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(name: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+
+        data.extend_from_slice(name);
+        data.extend_from_slice(body);
+
+        return data;
+    }
+
+    fn chpl(chapters: &[(u64, &str)]) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 0, chapters.len() as u8];
+
+        for (start, title) in chapters {
+            body.extend_from_slice(&start.to_be_bytes());
+            body.push(title.len() as u8);
+            body.extend_from_slice(title.as_bytes());
+        }
+
+        return body;
+    }
+
+    #[test]
+    fn reads_chpl_chapters() {
+        let moov = mp4_box(b"udta", &mp4_box(b"chpl", &chpl(&[(0, "Intro"), (3_050_000_000, "Middle")])));
+        let chapters = read_chpl_chapters(&moov).unwrap();
+
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, "Intro");
+        assert_eq!(chapters[1].start, Duration::from_secs(305));
+        assert_eq!(chapters[1].title, "Middle");
+    }
+
+    #[test]
+    fn truncated_chpl_is_rejected() {
+        let mut body = chpl(&[(0, "Intro"), (3_050_000_000, "Middle")]);
+
+        // The last title runs past the end of the box
+        body.truncate(body.len() - 3);
+
+        let moov = mp4_box(b"udta", &mp4_box(b"chpl", &body));
+
+        assert!(read_chpl_chapters(&moov).is_none());
+    }
+
+    #[test]
+    fn box_larger_than_its_parent_is_ignored() {
+        let mut moov = mp4_box(b"udta", &mp4_box(b"chpl", &chpl(&[(0, "Intro")])));
+
+        // The declared size now claims more bytes than there are
+        moov[3] += 16;
+
+        assert!(child_boxes(&moov).is_empty());
+        assert!(read_chpl_chapters(&moov).is_none());
+    }
+
+    #[test]
+    fn table_length_must_fit_in_the_box() {
+        let mut stsz = vec![0; 8];
+
+        stsz.extend_from_slice(&2u32.to_be_bytes());
+        stsz.extend_from_slice(&[0; 8]);
+
+        assert_eq!(table_length(&stsz, 8, 4), Some(2));
+        assert_eq!(table_length(&stsz, 8, 8), None);
+
+        stsz[11] = 200;
+
+        assert_eq!(table_length(&stsz, 8, 4), None);
+    }
+}
@@ -0,0 +1,429 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    env,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        mpsc::{
+            self,
+            Receiver,
+            Sender,
+        },
+    },
+    thread,
+};
+use image::{
+    imageops::FilterType,
+    RgbImage,
+};
+use lofty::{
+    read_from_path,
+    file::TaggedFileExt,
+    picture::PictureType,
+};
+use ratatui::prelude::*;
+
+/// File stems looked for next to the track, best first
+const COVER_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Covers are shrunk to this once loaded, terminals never need more
+const MAX_SIZE: u32 = 512;
+
+/// Kitty takes the picture in pieces of at most 4096 bytes
+const KITTY_CHUNK: usize = 4096;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Iterm,
+    Sixel,
+    HalfBlocks,
+}
+
+pub struct Cover {
+    /// Tells covers apart, so a picture is only sent to the terminal again when it changes
+    pub id: u64,
+    /// The file as stored, iTerm decodes it itself
+    data: Vec<u8>,
+    image: RgbImage,
+}
+
+impl Cover {
+    /// Picture embedded in the tags, or a cover file in the same folder
+    pub fn load(path: &Path) -> Option<Self> {
+        let data = embedded_picture(path).or_else(|| cover_file(path))?;
+        let image = image::load_from_memory(&data)
+            .ok()?
+            .thumbnail(MAX_SIZE, MAX_SIZE)
+            .to_rgb8();
+
+        return Some(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            data,
+            image,
+        });
+    }
+
+    /// The largest part of `area` with the shape of the picture, centered at the top
+    pub fn fit(&self, area: Rect) -> Rect {
+        let (cell_width, cell_height) = cell_size();
+        let (width, height) = self.image.dimensions();
+
+        let scale = (area.width as f64 * cell_width as f64 / width as f64)
+            .min(area.height as f64 * cell_height as f64 / height as f64);
+
+        let columns = ((width as f64 * scale / cell_width as f64).round() as u16).clamp(1, area.width.max(1));
+        let rows = ((height as f64 * scale / cell_height as f64).round() as u16).clamp(1, area.height.max(1));
+
+        return Rect {
+            x: area.x + (area.width - columns) / 2,
+            y: area.y,
+            width: columns,
+            height: rows,
+        };
+    }
+
+    /// Two pixels per cell, the upper one in `▀` and the lower one behind it
+    pub fn half_blocks(&self, area: Rect) -> Vec<Line<'static>> {
+        let image = image::imageops::resize(
+            &self.image,
+            area.width as u32,
+            area.height as u32 * 2,
+            FilterType::Triangle,
+        );
+
+        return (0..area.height as u32)
+            .map(|row| {
+                let spans: Vec<Span> = (0..area.width as u32)
+                    .map(|column| {
+                        let [r, g, b] = image.get_pixel(column, row * 2).0;
+                        let [r2, g2, b2] = image.get_pixel(column, row * 2 + 1).0;
+
+                        return Span::styled("▀", Style::default().fg(Color::Rgb(r, g, b)).bg(Color::Rgb(r2, g2, b2)));
+                    })
+                    .collect();
+
+                return Line::from(spans);
+            })
+            .collect();
+    }
+
+    /// Escape sequence that draws the picture over `area`, starting at the cursor
+    pub fn escape_sequence(&self, protocol: GraphicsProtocol, area: Rect) -> String {
+        let (cell_width, cell_height) = cell_size();
+        let pixels = || {
+            return image::imageops::resize(
+                &self.image,
+                area.width as u32 * cell_width,
+                area.height as u32 * cell_height,
+                FilterType::Triangle,
+            );
+        };
+
+        match protocol {
+            GraphicsProtocol::Kitty => {
+                let image = pixels();
+                let encoded = base64(image.as_raw());
+                let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+                let mut sequence = String::new();
+
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+
+                    if i == 0 {
+                        sequence.push_str(&format!(
+                            "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={};{}\x1b\\",
+                            image.width(),
+                            image.height(),
+                            area.width,
+                            area.height,
+                            more,
+                            chunk,
+                        ));
+                    } else {
+                        sequence.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                    }
+                }
+
+                return sequence;
+            },
+            GraphicsProtocol::Iterm => {
+                return format!(
+                    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=0:{}\x07",
+                    self.data.len(),
+                    area.width,
+                    area.height,
+                    base64(&self.data),
+                );
+            },
+            GraphicsProtocol::Sixel => {
+                return sixel(&pixels());
+            },
+            GraphicsProtocol::HalfBlocks => {
+                return String::new();
+            },
+        }
+    }
+}
+
+/// Reads and scales covers on a worker thread, big embedded pictures take a while to decode
+pub struct CoverLoader {
+    requests: Sender<PathBuf>,
+    results: Receiver<(PathBuf, Option<Cover>)>,
+}
+
+impl CoverLoader {
+    pub fn new() -> Self {
+        let (requests, request_receiver) = mpsc::channel::<PathBuf>();
+        let (result_sender, results) = mpsc::channel();
+
+        // Only the newest track's cover is worth loading
+        thread::spawn(move || {
+            while let Ok(mut path) = request_receiver.recv() {
+                while let Ok(newer) = request_receiver.try_recv() {
+                    path = newer;
+                }
+
+                let cover = Cover::load(&path);
+
+                if result_sender.send((path, cover)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        return Self {
+            requests,
+            results,
+        };
+    }
+
+    pub fn request(&self, path: &Path) {
+        let _ = self.requests.send(path.to_path_buf());
+    }
+
+    /// The next cover loaded, with the track it was loaded for
+    pub fn loaded(&self) -> Option<(PathBuf, Option<Cover>)> {
+        return self.results.try_recv().ok();
+    }
+}
+
+/// `auto` guesses from the environment, anything unknown turns covers off
+pub fn graphics_protocol(setting: &str) -> Option<GraphicsProtocol> {
+    match setting.to_lowercase().as_str() {
+        "auto" => {
+            return Some(detect_protocol());
+        },
+        "kitty" => {
+            return Some(GraphicsProtocol::Kitty);
+        },
+        "iterm" => {
+            return Some(GraphicsProtocol::Iterm);
+        },
+        "sixel" => {
+            return Some(GraphicsProtocol::Sixel);
+        },
+        "blocks" | "halfblocks" => {
+            return Some(GraphicsProtocol::HalfBlocks);
+        },
+        _ => {
+            return None;
+        },
+    }
+}
+
+/// Deletes every picture the protocol keeps apart from the text
+pub fn clear_sequence(protocol: GraphicsProtocol) -> &'static str {
+    match protocol {
+        GraphicsProtocol::Kitty => {
+            return "\x1b_Ga=d,q=2\x1b\\";
+        },
+        _ => {
+            return "";
+        },
+    }
+}
+
+fn detect_protocol() -> GraphicsProtocol {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    let program = var("TERM_PROGRAM");
+
+    // Multiplexers swallow the sequences, but pass on the variables of the terminal around them
+    if env::var_os("TMUX").is_some() || env::var_os("STY").is_some() {
+        return GraphicsProtocol::HalfBlocks;
+    }
+
+    if env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "ghostty" {
+        return GraphicsProtocol::Kitty;
+    }
+
+    if program == "iTerm.app" || program == "WezTerm" || var("LC_TERMINAL") == "iTerm2" {
+        return GraphicsProtocol::Iterm;
+    }
+
+    if ["foot", "mlterm", "contour", "yaft"].iter().any(|t| term.starts_with(t)) {
+        return GraphicsProtocol::Sixel;
+    }
+
+    return GraphicsProtocol::HalfBlocks;
+}
+
+/// Pixel size of a terminal cell, or a common guess when the terminal doesn't tell
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            return (
+                (size.width / size.columns).max(1) as u32,
+                (size.height / size.rows).max(1) as u32,
+            );
+        },
+        _ => {
+            return (8, 16);
+        },
+    }
+}
+
+fn embedded_picture(path: &Path) -> Option<Vec<u8>> {
+    let tagged_file = read_from_path(path).ok()?;
+    let pictures: Vec<_> = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|tag| tag.pictures())
+        .collect();
+
+    return pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first())
+        .map(|p| p.data().to_vec());
+}
+
+fn cover_file(path: &Path) -> Option<Vec<u8>> {
+    let files: Vec<(String, String, PathBuf)> = std::fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+            let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+            return Some((stem, extension, path));
+        })
+        .filter(|(_, extension, _)| COVER_EXTENSIONS.contains(&extension.as_str()))
+        .collect();
+
+    return COVER_NAMES
+        .iter()
+        .find_map(|name| files.iter().find(|(stem, _, _)| stem == name))
+        .and_then(|(_, _, path)| std::fs::read(path).ok());
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - i * 6) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    return encoded;
+}
+
+/// Sixel image with the colors rounded to a 6×6×6 cube
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let indices: Vec<u8> = image
+        .pixels()
+        .map(|p| (level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])) as u8)
+        .collect();
+
+    let mut sequence = format!("\x1bPq\"1;1;{};{}", width, height);
+
+    for i in 0..216 {
+        sequence.push_str(&format!("#{};2;{};{};{}", i, i / 36 * 20, i / 6 % 6 * 20, i % 6 * 20));
+    }
+
+    // Every band of six rows is drawn once per color in it
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut used = [false; 216];
+
+        for y in band..band + rows {
+            for x in 0..width {
+                used[indices[(y * width + x) as usize] as usize] = true;
+            }
+        }
+
+        for color in (0..216).filter(|&c| used[c]) {
+            sequence.push_str(&format!("#{}", color));
+
+            let mut run: Option<(char, u32)> = None;
+
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|dy| indices[((band + dy) * width + x) as usize] as usize == color)
+                    .fold(0, |bits, dy| bits | 1 << dy);
+                let c = char::from(63 + bits as u8);
+
+                run = match run {
+                    Some((previous, count)) if previous == c => Some((c, count + 1)),
+                    Some((previous, count)) => {
+                        push_run(&mut sequence, previous, count);
+
+                        Some((c, 1))
+                    },
+                    None => Some((c, 1)),
+                };
+            }
+
+            if let Some((c, count)) = run {
+                push_run(&mut sequence, c, count);
+            }
+
+            sequence.push('$');
+        }
+
+        sequence.push('-');
+    }
+
+    sequence.push_str("\x1b\\");
+
+    return sequence;
+}
+
+fn push_run(sequence: &mut String, c: char, count: u32) {
+    if count > 3 {
+        sequence.push_str(&format!("!{}{}", count, c));
+    } else {
+        sequence.extend(std::iter::repeat_n(c, count as usize));
+    }
+}
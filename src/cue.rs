@@ -0,0 +1,370 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+use lofty::{
+    read_from_path,
+    file::TaggedFileExt,
+    tag::ItemKey,
+};
+
+use crate::{
+    get_audio_duration,
+    playlist::read_text,
+    Metadata,
+    Track,
+};
+
+#[derive(Default)]
+struct CueSheet {
+    title: Option<String>,
+    performer: Option<String>,
+    genre: Option<String>,
+    date: Option<String>,
+    tracks: Vec<CueTrack>,
+}
+
+#[derive(Default)]
+struct CueTrack {
+    file: String,
+    number: u32,
+    title: Option<String>,
+    performer: Option<String>,
+    start: Duration,
+}
+
+pub fn is_cue_file(path: &Path) -> bool {
+    return path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+}
+
+/// Splits the audio files referenced by an external `.cue` file into tracks
+pub fn load_cue_file(path: &Path) -> Vec<Track> {
+    let Ok(contents) = read_text(path) else {
+        return Vec::new();
+    };
+
+    let base_directory = path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+
+    let sheet = parse_cue(&contents);
+    let mut tracks = Vec::new();
+    let mut files: Vec<&str> = sheet
+        .tracks
+        .iter()
+        .map(|t| t.file.as_str())
+        .collect();
+
+    files.dedup();
+
+    for file in files {
+        let file_path = resolve_file(file, &base_directory, path);
+
+        if let Some(file_path) = file_path {
+            tracks.extend(split_file(&file_path, &sheet, file));
+        }
+    }
+
+    return tracks;
+}
+
+/// Splits a file with an embedded `CUESHEET` tag into tracks, if it has one
+pub fn load_embedded_cue(path: &Path) -> Option<Vec<Track>> {
+    let tagged_file = read_from_path(path).ok()?;
+
+    let contents = tagged_file
+        .tags()
+        .iter()
+        .flat_map(|tag| tag.items())
+        .find_map(|item| {
+            match item.key() {
+                ItemKey::Unknown(key) if key.eq_ignore_ascii_case("cuesheet") => {
+                    return item.value().text();
+                },
+                _ => {
+                    return None;
+                },
+            }
+        })?;
+
+    let mut sheet = parse_cue(contents);
+
+    // The embedded sheet always describes the file it is stored in
+    for track in &mut sheet.tracks {
+        track.file.clear();
+    }
+
+    let tracks = split_file(path, &sheet, "");
+
+    if tracks.is_empty() {
+        return None;
+    }
+
+    return Some(tracks);
+}
+
+fn split_file(path: &Path, sheet: &CueSheet, file: &str) -> Vec<Track> {
+    let file_tracks: Vec<&CueTrack> = sheet
+        .tracks
+        .iter()
+        .filter(|t| t.file == file)
+        .collect();
+
+    if file_tracks.is_empty() {
+        return Vec::new();
+    }
+
+    let base_metadata = Metadata::from_tags(path);
+    let file_duration = get_audio_duration(path).unwrap_or(Duration::ZERO);
+    let mut tracks = Vec::new();
+
+    for (i, cue_track) in file_tracks.iter().enumerate() {
+        let end = file_tracks
+            .get(i + 1)
+            .map(|next| next.start);
+
+        let duration = match end {
+            Some(end) => end.saturating_sub(cue_track.start),
+            None => file_duration.saturating_sub(cue_track.start),
+        };
+
+        let mut metadata = base_metadata.clone();
+
+        metadata.title = cue_track
+            .title
+            .clone()
+            .or_else(|| Some(format!("Track {:02}", cue_track.number)));
+        metadata.artist = cue_track
+            .performer
+            .clone()
+            .or_else(|| sheet.performer.clone())
+            .or(metadata.artist);
+        metadata.album_artist = sheet.performer.clone().or(metadata.album_artist);
+        metadata.album = sheet.title.clone().or(metadata.album);
+        metadata.year = sheet.date.clone().or(metadata.year);
+        metadata.genre = sheet.genre.clone().or(metadata.genre);
+        metadata.track_number = Some(cue_track.number);
+
+        tracks.push(Track {
+            path: path.to_path_buf(),
+            start: cue_track.start,
+            end,
+            duration,
+            metadata,
+        });
+    }
+
+    return tracks;
+}
+
+fn parse_cue(contents: &str) -> CueSheet {
+    let mut sheet = CueSheet::default();
+    let mut current_file = String::new();
+    let mut current_track: Option<CueTrack> = None;
+
+    for line in contents.lines() {
+        let line = line.trim();
+        let (command, rest) = line
+            .split_once(char::is_whitespace)
+            .unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command.to_uppercase().as_str() {
+            "FILE" => {
+                // `FILE "name.flac" WAVE`, the name may contain spaces
+                current_file = match rest.rsplit_once(char::is_whitespace) {
+                    Some((name, _)) => unquote(name),
+                    None => unquote(rest),
+                };
+            },
+            "TRACK" => {
+                if let Some(track) = current_track.take() {
+                    sheet.tracks.push(track);
+                }
+
+                let number = rest
+                    .split_whitespace()
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(sheet.tracks.len() as u32 + 1);
+
+                current_track = Some(CueTrack {
+                    file: current_file.clone(),
+                    number,
+                    ..Default::default()
+                });
+            },
+            "TITLE" => {
+                match &mut current_track {
+                    Some(track) => {
+                        track.title = Some(unquote(rest));
+                    },
+                    None => {
+                        sheet.title = Some(unquote(rest));
+                    },
+                }
+            },
+            "PERFORMER" => {
+                match &mut current_track {
+                    Some(track) => {
+                        track.performer = Some(unquote(rest));
+                    },
+                    None => {
+                        sheet.performer = Some(unquote(rest));
+                    },
+                }
+            },
+            "INDEX" => {
+                let mut parts = rest.split_whitespace();
+                let index = parts.next().unwrap_or("");
+                let time = parts.next().and_then(parse_cue_time);
+
+                if let (Some(track), "01", Some(time)) = (&mut current_track, index, time) {
+                    track.start = time;
+                }
+            },
+            "REM" if current_track.is_none() => {
+                let (key, value) = rest
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((rest, ""));
+
+                match key.to_uppercase().as_str() {
+                    "GENRE" => {
+                        sheet.genre = Some(unquote(value));
+                    },
+                    "DATE" => {
+                        sheet.date = Some(unquote(value));
+                    },
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if let Some(track) = current_track.take() {
+        sheet.tracks.push(track);
+    }
+
+    return sheet;
+}
+
+/// Parses `mm:ss:ff` where a frame is 1/75 of a second
+fn parse_cue_time(time: &str) -> Option<Duration> {
+    let mut parts = time.split(':');
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    let frames: u64 = parts.next()?.parse().ok()?;
+
+    return Some(
+        Duration::from_secs(minutes * 60 + seconds)
+            + Duration::from_nanos(frames * 1_000_000_000 / 75)
+    );
+}
+
+fn resolve_file(file: &str, base_directory: &Path, cue_path: &Path) -> Option<PathBuf> {
+    let path = base_directory.join(file);
+
+    if path.is_file() {
+        return Some(path);
+    }
+
+    // Rips are often re-encoded without updating the sheet, e.g. `.wav` to `.flac`
+    let stem = Path::new(file)
+        .file_stem()
+        .or_else(|| cue_path.file_stem())?;
+
+    let extensions = [
+        "flac",
+        "wav",
+        "mp3",
+        "m4a",
+        "aiff",
+        "aif",
+    ];
+
+    return extensions
+        .iter()
+        .map(|ext| base_directory.join(format!("{}.{}", stem.to_string_lossy(), ext)))
+        .find(|p| p.is_file());
+}
+
+fn unquote(value: &str) -> String {
+    return value
+        .trim()
+        .trim_matches('"')
+        .to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_file_names_keep_their_spaces() {
+        let sheet = parse_cue(concat!(
+            "PERFORMER \"Some Band\"\n",
+            "TITLE \"Live Album\"\n",
+            "FILE \"01 Side A.flac\" WAVE\n",
+            "  TRACK 01 AUDIO\n",
+            "    TITLE \"Opening\"\n",
+            "    INDEX 01 00:00:00\n",
+            "FILE \"02 Side B.flac\" WAVE\n",
+            "  TRACK 02 AUDIO\n",
+            "    INDEX 01 00:00:00\n",
+        ));
+
+        assert_eq!(sheet.performer.as_deref(), Some("Some Band"));
+        assert_eq!(sheet.title.as_deref(), Some("Live Album"));
+        assert_eq!(sheet.tracks.len(), 2);
+        assert_eq!(sheet.tracks[0].file, "01 Side A.flac");
+        assert_eq!(sheet.tracks[0].title.as_deref(), Some("Opening"));
+        assert_eq!(sheet.tracks[1].file, "02 Side B.flac");
+        assert_eq!(sheet.tracks[1].number, 2);
+    }
+
+    #[test]
+    fn index_frames_are_75ths_of_a_second() {
+        assert_eq!(parse_cue_time("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_cue_time("01:02:00"), Some(Duration::from_secs(62)));
+        assert_eq!(
+            parse_cue_time("00:01:15"),
+            Some(Duration::from_secs(1) + Duration::from_millis(200))
+        );
+        assert_eq!(
+            parse_cue_time("00:00:01"),
+            Some(Duration::from_nanos(13_333_333))
+        );
+        assert_eq!(parse_cue_time("00:01"), None);
+        assert_eq!(parse_cue_time("aa:00:00"), None);
+    }
+
+    #[test]
+    fn tracks_start_at_index_01() {
+        let sheet = parse_cue(concat!(
+            "FILE \"album.wav\" WAVE\n",
+            "  TRACK 01 AUDIO\n",
+            "    INDEX 01 00:00:00\n",
+            "  TRACK 02 AUDIO\n",
+            "    INDEX 00 03:58:50\n",
+            "    INDEX 01 04:00:00\n",
+        ));
+
+        assert_eq!(sheet.tracks[1].start, Duration::from_secs(240));
+    }
+}
@@ -0,0 +1,231 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use ratatui::widgets::ListState;
+
+use crate::{
+    cue::is_cue_file,
+    is_music_file,
+    playlist::is_playlist_file,
+};
+
+/// File manager style view of the music directories
+pub struct DirectoryBrowser {
+    roots: Vec<PathBuf>,
+    /// `None` lists the roots themselves when there is more than one
+    pub current: Option<PathBuf>,
+    pub entries: Vec<DirectoryEntry>,
+    pub state: ListState,
+}
+
+pub struct DirectoryEntry {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_directory: bool,
+}
+
+impl DirectoryBrowser {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let mut roots: Vec<PathBuf> = Vec::new();
+
+        for path in paths {
+            // Files and playlists given on the command line are browsed from their folder
+            let root = if path.is_dir() {
+                path.clone()
+            } else {
+                match path.parent() {
+                    Some(parent) if parent.is_dir() => parent.to_path_buf(),
+                    _ => continue,
+                }
+            };
+
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+
+        let current = if roots.len() == 1 {
+            roots.first().cloned()
+        } else {
+            None
+        };
+
+        let mut browser = Self {
+            roots,
+            current,
+            entries: Vec::new(),
+            state: ListState::default().with_selected(Some(0)),
+        };
+
+        browser.refresh();
+
+        return browser;
+    }
+
+    pub fn refresh(&mut self) {
+        self.entries = match &self.current {
+            Some(directory) => read_entries(directory),
+            None => self
+                .roots
+                .iter()
+                .map(|root| DirectoryEntry {
+                    path: root.clone(),
+                    name: root.display().to_string(),
+                    is_directory: true,
+                })
+                .collect(),
+        };
+
+        let selected = self
+            .state
+            .selected()
+            .unwrap_or(0)
+            .min(self.entries.len().saturating_sub(1));
+
+        self.state.select(Some(selected));
+    }
+
+    /// The music directories, files given on the command line count as their folder
+    pub fn roots(&self) -> &[PathBuf] {
+        return &self.roots;
+    }
+
+    pub fn selected(&self) -> Option<&DirectoryEntry> {
+        return self.entries.get(self.state.selected()?);
+    }
+
+    pub fn move_selection(&mut self, offset: i32) {
+        let len = self.entries.len() as i32;
+
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.state.selected().unwrap_or(0) as i32;
+
+        self.state.select(Some((selected + offset).clamp(0, len - 1) as usize));
+    }
+
+    /// Enters the selected directory, returns `false` if a file is selected
+    pub fn open(&mut self) -> bool {
+        let Some(entry) = self.selected() else {
+            return false;
+        };
+
+        if !entry.is_directory {
+            return false;
+        }
+
+        self.current = Some(entry.path.clone());
+        self.state.select(Some(0));
+        self.refresh();
+
+        return true;
+    }
+
+    /// Goes up one level, but never above the music directories
+    pub fn parent(&mut self) {
+        let Some(current) = self.current.clone() else {
+            return;
+        };
+
+        if self.roots.contains(&current) {
+            if self.roots.len() > 1 {
+                self.current = None;
+            } else {
+                return;
+            }
+        } else {
+            self.current = current.parent().map(|p| p.to_path_buf());
+        }
+
+        self.refresh();
+
+        // Keep the directory we came from selected
+        let index = self
+            .entries
+            .iter()
+            .position(|e| e.path == current)
+            .unwrap_or(0);
+
+        self.state.select(Some(index));
+    }
+
+    /// The directory to play: the selected one, or the current one when a file is selected
+    pub fn target_directory(&self) -> Option<PathBuf> {
+        match self.selected() {
+            Some(entry) if entry.is_directory => {
+                return Some(entry.path.clone());
+            },
+            _ => {
+                return self.current.clone();
+            },
+        }
+    }
+
+    /// Path of the current directory relative to its root, for the panel title
+    pub fn location(&self) -> Option<String> {
+        let current = self.current.as_ref()?;
+        let root = self
+            .roots
+            .iter()
+            .filter(|r| current.starts_with(r))
+            .max_by_key(|r| r.components().count())?;
+
+        let relative = current
+            .strip_prefix(root)
+            .unwrap_or(Path::new(""));
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| root.display().to_string());
+
+        if relative.as_os_str().is_empty() {
+            return Some(name);
+        }
+
+        return Some(format!("{}/{}", name, relative.display()));
+    }
+}
+
+fn read_entries(directory: &Path) -> Vec<DirectoryEntry> {
+    let Ok(read_dir) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut entries: Vec<DirectoryEntry> = read_dir
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            let name = e.file_name().to_string_lossy().to_string();
+            let is_directory = path.is_dir();
+
+            if name.starts_with('.') {
+                return None;
+            }
+
+            if !is_directory && !is_music_file(&path) && !is_cue_file(&path) && !is_playlist_file(&path) {
+                return None;
+            }
+
+            return Some(DirectoryEntry {
+                path,
+                name,
+                is_directory,
+            });
+        })
+        .collect();
+
+    entries.sort_by_key(|e| (!e.is_directory, e.name.to_lowercase()));
+
+    return entries;
+}
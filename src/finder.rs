@@ -0,0 +1,314 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::cell::Cell;
+use ratatui::widgets::ListState;
+
+use crate::Track;
+
+const SCORE_MATCH: i64 = 16;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+/// Fuzzy finder popup over the whole library
+pub struct Finder {
+    pub query: String,
+    /// Candidate text for every library track, `artist - album - title - path`
+    candidates: Vec<String>,
+    /// Library indices, best first
+    pub results: Vec<usize>,
+    pub state: ListState,
+    /// First result on screen
+    offset: Cell<usize>,
+}
+
+impl Finder {
+    pub fn new(library: &[Track]) -> Self {
+        let candidates = library
+            .iter()
+            .map(|track| {
+                let metadata = &track.metadata;
+
+                return format!(
+                    "{} - {} - {} - {}",
+                    metadata.artist.as_deref().unwrap_or("Unknown Artist"),
+                    metadata.album.as_deref().unwrap_or("Unknown Album"),
+                    metadata.title.as_deref().unwrap_or("Unknown"),
+                    track.path.display(),
+                );
+            })
+            .collect();
+
+        let mut finder = Self {
+            query: String::new(),
+            candidates,
+            results: Vec::new(),
+            state: ListState::default().with_selected(Some(0)),
+            offset: Cell::new(0),
+        };
+
+        finder.update();
+
+        return finder;
+    }
+
+    pub fn candidate(&self, index: usize) -> &str {
+        return &self.candidates[index];
+    }
+
+    /// Re-ranks the candidates for the current query
+    pub fn update(&mut self) {
+        let query = self.query_chars();
+
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, candidate)| Some((fuzzy_score(candidate, &query)?, i)))
+            .collect();
+
+        // Shorter candidates win ties, like fzf
+        scored.sort_by_key(|(score, i)| (-score, self.candidates[*i].len(), *i));
+
+        self.results = scored
+            .into_iter()
+            .map(|(_, i)| i)
+            .collect();
+        self.state.select(Some(0));
+    }
+
+    /// The results that fit in `height` rows with their matched positions, and the selected row among them
+    ///
+    /// Positions are only worked out for these, the rest of the results just need their order
+    pub fn visible(&self, height: usize) -> (Vec<(usize, Vec<usize>)>, ListState) {
+        let selected = self.state.selected().unwrap_or(0);
+        let mut offset = self.offset.get();
+
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + height {
+            offset = (selected + 1).saturating_sub(height);
+        }
+
+        self.offset.set(offset);
+
+        let query = self.query_chars();
+        let rows = self
+            .results
+            .iter()
+            .skip(offset)
+            .take(height)
+            .map(|&i| {
+                let positions = fuzzy_match(&self.candidates[i], &query)
+                    .map(|(_, positions)| positions)
+                    .unwrap_or_default();
+
+                return (i, positions);
+            })
+            .collect();
+
+        return (rows, ListState::default().with_selected(Some(selected - offset)));
+    }
+
+    fn query_chars(&self) -> Vec<char> {
+        return self
+            .query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(|c| c.to_lowercase())
+            .collect();
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        return self
+            .results
+            .get(self.state.selected()?)
+            .copied();
+    }
+
+    pub fn move_selection(&mut self, offset: i32) {
+        let len = self.results.len() as i32;
+
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.state.selected().unwrap_or(0) as i32;
+
+        self.state.select(Some((selected + offset).clamp(0, len - 1) as usize));
+    }
+}
+
+/// Scores the query as a subsequence of the text, `None` if it isn't one
+///
+/// Same idea as fzf: matches at word boundaries and consecutive matches score higher,
+/// gaps between matches cost a little
+pub fn fuzzy_score(text: &str, query: &[char]) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    // Most candidates don't match at all, tell before allocating anything
+    if !is_subsequence(text, query) {
+        return None;
+    }
+
+    let original: Vec<char> = text.chars().collect();
+    let last_row = score_rows(&original, query, |_| {});
+
+    return last_row
+        .into_iter()
+        .filter(|s| *s != i64::MIN)
+        .max();
+}
+
+/// Same as `fuzzy_score`, with the matched char positions for highlighting
+pub fn fuzzy_match(text: &str, query: &[char]) -> Option<(i64, Vec<usize>)> {
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    if !is_subsequence(text, query) {
+        return None;
+    }
+
+    let original: Vec<char> = text.chars().collect();
+    let mut previous = Vec::with_capacity(query.len());
+    let last_row = score_rows(&original, query, |row_previous| previous.push(row_previous.to_vec()));
+
+    let (mut j, score) = last_row
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| **s != i64::MIN)
+        .max_by_key(|(j, s)| (**s, std::cmp::Reverse(*j)))
+        .map(|(j, s)| (j, *s))?;
+
+    let mut positions = vec![0; query.len()];
+
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        j = previous[i][j];
+    }
+
+    return Some((score, positions));
+}
+
+fn is_subsequence(text: &str, query: &[char]) -> bool {
+    let mut remaining = query.iter().peekable();
+
+    for c in text.chars().map(lowercase) {
+        if remaining.peek() == Some(&&c) {
+            remaining.next();
+        }
+    }
+
+    return remaining.peek().is_none();
+}
+
+fn lowercase(c: char) -> char {
+    return c.to_lowercase().next().unwrap_or(c);
+}
+
+/// Scores one query char at a time keeping only the row before, returns the last row
+///
+/// `row[j]` is the best score with the query char matched at `chars[j]`, `on_row` gets
+/// where every match's predecessor was, which is all the positions need
+fn score_rows(original: &[char], query: &[char], mut on_row: impl FnMut(&[usize])) -> Vec<i64> {
+    let chars: Vec<char> = original
+        .iter()
+        .map(|&c| lowercase(c))
+        .collect();
+    let bonuses: Vec<i64> = (0..original.len())
+        .map(|j| char_bonus(original, j))
+        .collect();
+
+    let n = chars.len();
+    let mut last_row = vec![i64::MIN; n];
+    let mut row = vec![i64::MIN; n];
+    let mut previous = vec![usize::MAX; n];
+
+    for (i, &query_char) in query.iter().enumerate() {
+        // Best predecessor seen so far, already charged for the gap up to `j`
+        let mut carry: Option<(i64, usize)> = None;
+
+        row.fill(i64::MIN);
+        previous.fill(usize::MAX);
+
+        for j in 0..n {
+            if i > 0 && j > 0 {
+                carry = carry.map(|(score, k)| (score - PENALTY_GAP_EXTENSION, k));
+
+                if j >= 2 && last_row[j - 2] != i64::MIN {
+                    let gapped = last_row[j - 2] - PENALTY_GAP_START;
+
+                    if carry.is_none_or(|(score, _)| gapped > score) {
+                        carry = Some((gapped, j - 2));
+                    }
+                }
+            }
+
+            if chars[j] != query_char {
+                continue;
+            }
+
+            let bonus = if i == 0 {
+                bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER
+            } else {
+                bonuses[j]
+            };
+
+            if i == 0 {
+                row[j] = SCORE_MATCH + bonus;
+
+                continue;
+            }
+
+            let consecutive = (j > 0 && last_row[j - 1] != i64::MIN)
+                .then(|| (last_row[j - 1] + BONUS_CONSECUTIVE.max(bonus), j - 1));
+
+            let best = match (consecutive, carry) {
+                (Some(a), Some(b)) if b.0 + bonus > a.0 => Some((b.0 + bonus, b.1)),
+                (Some(a), _) => Some(a),
+                (None, Some(b)) => Some((b.0 + bonus, b.1)),
+                (None, None) => None,
+            };
+
+            if let Some((score, k)) = best {
+                row[j] = score + SCORE_MATCH;
+                previous[j] = k;
+            }
+        }
+
+        on_row(&previous);
+        std::mem::swap(&mut last_row, &mut row);
+    }
+
+    return last_row;
+}
+
+fn char_bonus(chars: &[char], j: usize) -> i64 {
+    let Some(&before) = j.checked_sub(1).and_then(|k| chars.get(k)) else {
+        return BONUS_BOUNDARY;
+    };
+
+    let current = chars[j];
+
+    if !before.is_alphanumeric() && current.is_alphanumeric() {
+        return BONUS_BOUNDARY;
+    }
+
+    if before.is_lowercase() && current.is_uppercase() {
+        return BONUS_CAMEL;
+    }
+
+    return 0;
+}
@@ -0,0 +1,270 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    path::Path,
+    time::Duration,
+};
+use lofty::{
+    read_from_path,
+    file::TaggedFileExt,
+    id3::v2::{
+        FrameFlags,
+        SynchronizedTextFrame,
+        TimestampFormat,
+    },
+    tag::ItemKey,
+};
+
+use crate::{
+    chapters::{
+        id3_frames,
+        read_id3_tag,
+    },
+    playlist::read_text,
+};
+
+pub enum Lyrics {
+    /// Lines with the time they start at, in order
+    Synced(Vec<(Duration, String)>),
+    Plain(Vec<String>),
+}
+
+/// A `.lrc` file next to the track, or lyrics from its tags
+pub fn load_lyrics(path: &Path) -> Option<Lyrics> {
+    let lrc = ["lrc", "LRC"]
+        .iter()
+        .find_map(|extension| read_text(&path.with_extension(extension)).ok());
+
+    if let Some(text) = lrc {
+        return Some(parse_lyrics(&text));
+    }
+
+    if let Some(lines) = read_sylt(path) {
+        return Some(Lyrics::Synced(lines));
+    }
+
+    let tagged_file = read_from_path(path).ok()?;
+    let text = tagged_file
+        .tags()
+        .iter()
+        .find_map(|tag| tag.get_string(&ItemKey::Lyrics).filter(|s| !s.trim().is_empty()))?
+        .to_string();
+
+    return Some(parse_lyrics(&text));
+}
+
+/// Index of the line being sung at `position`
+pub fn current_line(lines: &[(Duration, String)], position: Duration) -> Option<usize> {
+    return lines
+        .iter()
+        .rposition(|(time, _)| *time <= position);
+}
+
+/// LRC when any line has a timestamp, plain text otherwise
+fn parse_lyrics(text: &str) -> Lyrics {
+    let mut offset: i64 = 0;
+    let mut lines = Vec::new();
+    let mut plain = Vec::new();
+
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some(end) = tag.find(']') else {
+                break;
+            };
+
+            let content = &tag[..end];
+
+            if let Some(time) = parse_timestamp(content) {
+                times.push(time);
+            } else if let Some(value) = content.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            } else if !content.contains(':') {
+                break;
+            }
+
+            rest = tag[end + 1..].trim_start();
+        }
+
+        let text = strip_word_times(rest);
+
+        if times.is_empty() {
+            // Metadata lines like `[ar:Artist]` leave nothing behind
+            if !line.trim_start().starts_with('[') || !rest.is_empty() {
+                plain.push(text);
+            }
+        } else {
+            lines.extend(times.into_iter().map(|time| (time, text.clone())));
+        }
+    }
+
+    if lines.is_empty() {
+        return Lyrics::Plain(plain);
+    }
+
+    // A positive offset shows the lines earlier
+    for (time, _) in &mut lines {
+        *time = if offset >= 0 {
+            time.saturating_sub(Duration::from_millis(offset as u64))
+        } else {
+            *time + Duration::from_millis(offset.unsigned_abs())
+        };
+    }
+
+    lines.sort_by_key(|(time, _)| *time);
+
+    return Lyrics::Synced(lines);
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss:xx`
+fn parse_timestamp(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds
+        .trim()
+        .replacen(':', ".", 1)
+        .parse()
+        .ok()?;
+
+    if !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+
+    return Some(Duration::from_secs(minutes * 60) + Duration::from_secs_f64(seconds));
+}
+
+/// Enhanced LRC times every word with `<mm:ss.xx>`, only whole lines are shown
+fn strip_word_times(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+
+        if parse_timestamp(&rest[start + 1..start + end]).is_none() {
+            result.push_str(&rest[..start + end + 1]);
+        } else {
+            result.push_str(&rest[..start]);
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+
+    return result.trim().to_string();
+}
+
+/// ID3v2 `SYLT` frames, which lofty leaves unparsed
+fn read_sylt(path: &Path) -> Option<Vec<(Duration, String)>> {
+    let (tag, version) = read_id3_tag(path)?;
+
+    for (id, body) in id3_frames(&tag, version) {
+        if &id != b"SYLT" {
+            continue;
+        }
+
+        // Times in MPEG frames would need the frame rate
+        let Ok(frame) = SynchronizedTextFrame::parse(body, FrameFlags::default()) else {
+            continue;
+        };
+
+        if frame.timestamp_format != TimestampFormat::MS || frame.content.is_empty() {
+            continue;
+        }
+
+        let mut lines: Vec<(Duration, String)> = frame
+            .content
+            .into_iter()
+            .map(|(time, text)| (Duration::from_millis(time as u64), text.trim().to_string()))
+            .collect();
+
+        lines.sort_by_key(|(time, _)| *time);
+
+        return Some(lines);
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced(text: &str) -> Vec<(u128, String)> {
+        let Lyrics::Synced(lines) = parse_lyrics(text) else {
+            panic!("Expected synced lyrics");
+        };
+
+        return lines
+            .into_iter()
+            .map(|(time, text)| (time.as_millis(), text))
+            .collect();
+    }
+
+    #[test]
+    fn lines_with_several_timestamps_repeat() {
+        assert_eq!(
+            synced("[ar:Artist]\n[00:10.00][01:05.50]Chorus\n[00:20.25]Verse\n"),
+            vec![
+                (10_000, "Chorus".to_string()),
+                (20_250, "Verse".to_string()),
+                (65_500, "Chorus".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn positive_offset_shows_lines_earlier() {
+        assert_eq!(
+            synced("[offset:+500]\n[00:00.25]First\n[00:10.00]Second\n"),
+            vec![
+                (0, "First".to_string()),
+                (9_500, "Second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_offset_shows_lines_later() {
+        assert_eq!(
+            synced("[offset:-1500]\n[00:10.00]Line\n"),
+            vec![(11_500, "Line".to_string())]
+        );
+    }
+
+    #[test]
+    fn word_times_are_stripped() {
+        assert_eq!(
+            synced("[00:01:50]<00:01.50>Every <00:02.00>word <tag>\n"),
+            vec![(1_500, "Every word <tag>".to_string())]
+        );
+    }
+
+    #[test]
+    fn text_without_timestamps_is_plain() {
+        let Lyrics::Plain(lines) = parse_lyrics("[ar:Artist]\nFirst line\n\n[Chorus] sung twice\n") else {
+            panic!("Expected plain lyrics");
+        };
+
+        assert_eq!(lines, vec!["First line", "", "[Chorus] sung twice"]);
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        assert_eq!(parse_timestamp("01:02.50"), Some(Duration::from_millis(62_500)));
+        assert_eq!(parse_timestamp("00:60.00"), None);
+        assert_eq!(parse_timestamp("ar:Artist"), None);
+        assert_eq!(parse_timestamp("00:-1"), None);
+    }
+}
@@ -7,25 +7,44 @@ the Free Software Foundation, either version 3 of the License, or
 */
 
 use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
     env,
     path::{
         Path,
         PathBuf,
     },
+    sync::Arc,
     time::{
         Duration,
         Instant,
     }
 };
-use anyhow::Result;
+use anyhow::{
+    Context,
+    Result,
+};
 use crossterm::{
+    cursor::MoveTo,
     event::{
         self,
+        DisableMouseCapture,
+        EnableMouseCapture,
         Event,
         KeyCode,
         KeyEventKind,
+        MouseButton,
+        MouseEvent,
+        MouseEventKind,
     },
     execute,
+    queue,
+    style::Print,
     terminal::{
         disable_raw_mode,
         enable_raw_mode,
@@ -33,12 +52,18 @@ use crossterm::{
         LeaveAlternateScreen,
     },
 };
-use directories::ProjectDirs;
+use directories::{
+    ProjectDirs,
+    UserDirs,
+};
 use lofty::{
     read_from_path,
     file::AudioFile,
     file::TaggedFileExt,
-    tag::Accessor,
+    tag::{
+        Accessor,
+        ItemKey,
+    },
 };
 use ratatui::{
     prelude::*,
@@ -46,6 +71,7 @@ use ratatui::{
     widgets::{
         Block,
         Borders,
+        Clear,
         Gauge,
         List,
         ListItem,
@@ -70,15 +96,100 @@ use serde::{
 };
 use walkdir::WalkDir;
 
+mod browser;
+mod chapters;
+mod cover;
+mod cue;
+mod directory;
+mod finder;
+mod lyrics;
+mod pattern;
+mod playlist;
+mod tags;
+mod trash;
+mod visualizer;
+mod waveform;
+
+use browser::{
+    LibraryTree,
+    TreeRow,
+};
+use chapters::{
+    current_chapter,
+    read_chapters,
+    Chapter,
+};
+use cover::{
+    clear_sequence,
+    graphics_protocol,
+    Cover,
+    CoverLoader,
+    GraphicsProtocol,
+};
+use cue::{
+    is_cue_file,
+    load_cue_file,
+    load_embedded_cue,
+};
+use directory::DirectoryBrowser;
+use finder::Finder;
+use lyrics::{
+    current_line,
+    load_lyrics,
+    Lyrics,
+};
+use pattern::{
+    library_root,
+    parse_components,
+    PatternChange,
+    PatternKind,
+    PatternPreview,
+    NAME_PATTERN,
+};
+use playlist::{
+    append_to_playlist,
+    is_playlist_file,
+    load_playlist,
+    named_playlist_path,
+    named_playlists,
+    save_playlist,
+};
+use tags::{
+    write_tags,
+    TagEditor,
+    TagField,
+};
+use trash::{
+    restore_file,
+    trash_file,
+    TrashedFile,
+};
+use visualizer::{
+    scope_lines,
+    Meters,
+    SampleTap,
+    Spectrum,
+    VisualizerMode,
+};
+use waveform::{
+    waveform_lines,
+    Waveforms,
+};
+
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+const LYRICS_OFFSET_STEP: i64 = 100;
 
+#[derive(Clone)]
 struct Track {
     path: PathBuf,
+    start: Duration,
+    end: Option<Duration>,
     duration: Duration,
     metadata: Metadata,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct Metadata {
     title: Option<String>,
     artist: Option<String>,
@@ -86,14 +197,25 @@ struct Metadata {
     year: Option<String>,
     genre: Option<String>,
     track_number: Option<u32>,
+    track_total: Option<u32>,
+    disc_number: Option<u32>,
+    disc_total: Option<u32>,
+    album_artist: Option<String>,
+    composer: Option<String>,
+    conductor: Option<String>,
+    lyricist: Option<String>,
+    label: Option<String>,
+    bpm: Option<String>,
+    comment: Option<String>,
     bitrate: Option<u32>,
     sample_rate: Option<u32>,
+    bit_depth: Option<u8>,
     channels: Option<u8>,
 }
 
 impl Metadata {
     fn from_path(path: &Path) -> Self {
-        let mut metadata = Self::default();
+        let mut metadata = Self::from_tags(path);
 
         let file_name = path
             .file_stem()
@@ -101,6 +223,14 @@ impl Metadata {
             .unwrap_or("Unknown")
             .to_string();
 
+        metadata.fill_from_name(&file_name);
+
+        return metadata;
+    }
+
+    fn from_tags(path: &Path) -> Self {
+        let mut metadata = Self::default();
+
         if let Ok(tagged_file) = read_from_path(path) {
             let tag = tagged_file
                 .primary_tag()
@@ -113,25 +243,89 @@ impl Metadata {
                 metadata.year = tag.year().map(|y| y.to_string());
                 metadata.genre = tag.genre().map(|s| s.to_string());
                 metadata.track_number = tag.track();
+                metadata.track_total = tag.track_total();
+                metadata.disc_number = tag.disk();
+                metadata.disc_total = tag.disk_total();
+                metadata.comment = tag.comment().map(|s| s.to_string());
+
+                let text = |key: &ItemKey| {
+                    return tag
+                        .get_string(key)
+                        .map(|s| s.to_string())
+                        .filter(|s| !s.is_empty());
+                };
+
+                metadata.album_artist = text(&ItemKey::AlbumArtist);
+                metadata.composer = text(&ItemKey::Composer);
+                metadata.conductor = text(&ItemKey::Conductor);
+                metadata.lyricist = text(&ItemKey::Lyricist);
+                metadata.label = text(&ItemKey::Label).or_else(|| text(&ItemKey::Publisher));
+                metadata.bpm = text(&ItemKey::IntegerBpm).or_else(|| text(&ItemKey::Bpm));
             }
 
             let properties = tagged_file.properties();
 
             metadata.bitrate = properties.audio_bitrate();
             metadata.sample_rate = properties.sample_rate();
+            metadata.bit_depth = properties.bit_depth();
             metadata.channels = properties.channels();
         }
 
-        if metadata.title.is_none() {
-            if let Some((artist, title)) = file_name.split_once(" - ") {
-                metadata.title = Some(title.to_string());
-                metadata.artist = Some(artist.to_string());
-            } else {
-                metadata.title = Some(file_name);
-            }
+        return metadata;
+    }
+
+    fn fill_from_name(&mut self, name: &str) {
+        if self.title.is_some() {
+            return;
         }
 
-        return metadata;
+        match parse_components(NAME_PATTERN, &[name]) {
+            Some(values) => {
+                self.apply_values(&values);
+            },
+            None => {
+                self.title = Some(name.to_string());
+            },
+        }
+    }
+
+    /// Fields read by a tag pattern, as they would be after writing them
+    fn apply_values(&mut self, values: &[(TagField, String)]) {
+        let numbered = |value: &str| {
+            let (number, total) = value
+                .split_once('/')
+                .unwrap_or((value, ""));
+
+            return (number.parse().ok(), total.parse().ok());
+        };
+
+        for (field, value) in values {
+            let text = Some(value.clone()).filter(|v| !v.is_empty());
+
+            match field {
+                TagField::Title => {
+                    self.title = text;
+                },
+                TagField::Artist => {
+                    self.artist = text;
+                },
+                TagField::Album => {
+                    self.album = text;
+                },
+                TagField::Year => {
+                    self.year = text;
+                },
+                TagField::Genre => {
+                    self.genre = text;
+                },
+                TagField::Track => {
+                    (self.track_number, self.track_total) = numbered(value);
+                },
+                TagField::Disc => {
+                    (self.disc_number, self.disc_total) = numbered(value);
+                },
+            }
+        }
     }
 }
 
@@ -141,6 +335,7 @@ struct Config {
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 struct ConfigSettings {
     toggle_playback: String,
     toggle_repeat: String,
@@ -150,17 +345,83 @@ struct ConfigSettings {
     previous_track: String,
     next_track: String,
     hide_track: String,
+    undo: String,
+    toggle_hidden: String,
+    trash_track: String,
+    mark_track: String,
+    edit_tags: String,
+    tags_from_path: String,
+    rename_from_tags: String,
+    open_playlist: String,
+    save_playlist: String,
+    toggle_playlists: String,
+    switch_focus: String,
+    select: String,
+    new_playlist: String,
+    rename_playlist: String,
+    delete_playlist: String,
+    add_to_playlist: String,
+    previous_chapter: String,
+    next_chapter: String,
+    toggle_lyrics: String,
+    lyrics_earlier: String,
+    lyrics_later: String,
+    toggle_visualizer: String,
+    cycle_visualizer: String,
+    toggle_waveform: String,
+    cycle_sort: String,
+    toggle_sort_direction: String,
+    toggle_browser: String,
+    browser_expand: String,
+    browser_collapse: String,
+    enqueue: String,
+    toggle_directories: String,
+    search: String,
+    clear_search: String,
+    page_up: String,
+    page_down: String,
+    jump_to_top: String,
+    jump_to_bottom: String,
+    jump_to_playing: String,
+    center_cursor: String,
+    find_track: String,
+    play_directory: String,
+    play_directory_recursive: String,
     reload_config: String,
     quit: String,
+    library_paths: Vec<String>,
+    playlist_relative_paths: bool,
+    resume_min_duration: u64,
+    persist_hidden: bool,
+    file_pattern: String,
+    sort_modes: Vec<String>,
+    sort_descending: bool,
+    playing_indicator: String,
+    mouse_support: bool,
+    cover_art: String,
     show_app_title: bool,
     show_playlist_title: bool,
     show_playlist_scrollbar: bool,
+    show_playlists_panel: bool,
     show_metadata_title: bool,
     show_metadata_panel: bool,
+    show_lyrics_panel: bool,
+    show_visualizer: bool,
+    visualizer_position: String,
+    visualizer_mode: String,
+    visualizer_bands: usize,
+    visualizer_falloff: f64,
+    show_waveform: bool,
+    waveform_height: u16,
     show_progress_title: bool,
     app_title_format: String,
     playlist_title_format: String,
+    playlists_title_format: String,
+    browser_title_format: String,
+    directories_title_format: String,
     metadata_title_format: String,
+    lyrics_title_format: String,
+    visualizer_title_format: String,
     progress_title_format: String,
     app_title_alignment: String,
     playlist_title_alignment: String,
@@ -168,7 +429,9 @@ struct ConfigSettings {
     progress_title_alignment: String,
     app_title_color: String,
     playlist_color: String,
+    marked_color: String,
     metadata_color: String,
+    visualizer_color: String,
     progress_color: String,
     rounded_corners: bool,
 }
@@ -184,17 +447,90 @@ impl Default for ConfigSettings {
             previous_track: "up".into(),
             next_track: "down".into(),
             hide_track: "h".into(),
+            undo: "u".into(),
+            toggle_hidden: "v".into(),
+            trash_track: "delete".into(),
+            mark_track: "x".into(),
+            edit_tags: "w".into(),
+            tags_from_path: "f3".into(),
+            rename_from_tags: "f2".into(),
+            open_playlist: "o".into(),
+            save_playlist: "s".into(),
+            toggle_playlists: "p".into(),
+            switch_focus: "tab".into(),
+            select: "enter".into(),
+            new_playlist: "n".into(),
+            rename_playlist: "e".into(),
+            delete_playlist: "d".into(),
+            add_to_playlist: "a".into(),
+            previous_chapter: "[".into(),
+            next_chapter: "]".into(),
+            toggle_lyrics: "y".into(),
+            lyrics_earlier: "-".into(),
+            lyrics_later: "=".into(),
+            toggle_visualizer: ";".into(),
+            cycle_visualizer: "\\".into(),
+            toggle_waveform: "'".into(),
+            cycle_sort: "m".into(),
+            toggle_sort_direction: "i".into(),
+            toggle_browser: "b".into(),
+            browser_expand: ">".into(),
+            browser_collapse: "<".into(),
+            enqueue: "+".into(),
+            toggle_directories: "f".into(),
+            search: "/".into(),
+            clear_search: "esc".into(),
+            page_up: "pageup".into(),
+            page_down: "pagedown".into(),
+            jump_to_top: "home".into(),
+            jump_to_bottom: "end".into(),
+            jump_to_playing: ".".into(),
+            center_cursor: "z".into(),
+            find_track: "t".into(),
+            play_directory: "g".into(),
+            play_directory_recursive: "*".into(),
             reload_config: "c".into(),
             quit: "q".into(),
+            library_paths: Vec::new(),
+            playlist_relative_paths: true,
+            resume_min_duration: 1800,
+            persist_hidden: false,
+            file_pattern: "%artist%/%album%/%track% - %title%".into(),
+            sort_modes: vec![
+                "title".into(),
+                "albumartist,album,disc,track".into(),
+                "path".into(),
+                "year,album,disc,track".into(),
+                "added".into(),
+                "duration".into(),
+            ],
+            sort_descending: false,
+            playing_indicator: "▶ ".into(),
+            mouse_support: true,
+            cover_art: "auto".into(),
             show_app_title: true,
             show_playlist_title: true,
             show_playlist_scrollbar: true,
+            show_playlists_panel: false,
             show_metadata_title: true,
             show_metadata_panel: true,
+            show_lyrics_panel: false,
+            show_visualizer: false,
+            visualizer_position: "beside".into(),
+            visualizer_mode: "spectrum".into(),
+            visualizer_bands: 32,
+            visualizer_falloff: 1.5,
+            show_waveform: false,
+            waveform_height: 2,
             show_progress_title: false,
             app_title_format: "┤ Sonido v{VERSION} ├".into(),
             playlist_title_format: "┤ Playlist ├".into(),
+            playlists_title_format: "┤ Playlists ├".into(),
+            browser_title_format: "┤ Browser ├".into(),
+            directories_title_format: "┤ Files ├".into(),
             metadata_title_format: "┤ Metadata ├".into(),
+            lyrics_title_format: "┤ Lyrics ├".into(),
+            visualizer_title_format: "┤ Visualizer ├".into(),
             progress_title_format: "┤ Progress ├".into(),
             app_title_alignment: "center".into(),
             playlist_title_alignment: "left".into(),
@@ -202,7 +538,9 @@ impl Default for ConfigSettings {
             progress_title_alignment: "left".into(),
             app_title_color: "blue".into(),
             metadata_color: "blue".into(),
+            visualizer_color: "blue".into(),
             playlist_color: "blue".into(),
+            marked_color: "yellow".into(),
             progress_color: "blue".into(),
             rounded_corners: true,
         }
@@ -211,9 +549,18 @@ impl Default for ConfigSettings {
 
 struct App {
     tracks: Vec<Track>,
+    library: Vec<Track>,
     config: ConfigSettings,
     current_track: usize,
     list_state: ListState,
+    /// Scroll offset and height of the track list from the last draw
+    list_offset: Cell<usize>,
+    list_height: Cell<usize>,
+    /// Where the list and progress bar were drawn, for mouse clicks
+    list_area: Cell<Rect>,
+    progress_area: Cell<Rect>,
+    /// Position under the mouse while dragging on the progress bar
+    drag_position: Option<Duration>,
     playback_state: PlaybackState,
     position: Duration,
     playback_start: Option<Instant>,
@@ -221,6 +568,101 @@ struct App {
     sink: Option<Sink>,
     _stream: Option<OutputStream>,
     scroll_state: ScrollbarState,
+    prompt: Option<Prompt>,
+    message: Option<(String, Instant)>,
+    playlists: Vec<String>,
+    playlists_state: ListState,
+    active_playlist: Option<String>,
+    show_playlists: bool,
+    focus: Focus,
+    chapters: Vec<Chapter>,
+    chapters_path: Option<PathBuf>,
+    show_lyrics: bool,
+    lyrics: Option<Lyrics>,
+    lyrics_path: Option<PathBuf>,
+    /// Milliseconds the lyrics are shown later than the file says, to fix misaligned ones
+    lyrics_offset: i64,
+    show_visualizer: bool,
+    /// Samples on their way to the sink, for the visualizer
+    tap: SampleTap,
+    visualizer_mode: VisualizerMode,
+    spectrum: Spectrum,
+    meters: Meters,
+    show_waveform: bool,
+    waveforms: Waveforms,
+    /// Track the waveform was asked for, by path and start
+    waveform_track: Option<(PathBuf, Duration)>,
+    /// Peaks of the current track, once analyzed
+    waveform: Option<Arc<Vec<u8>>>,
+    cover: Option<Cover>,
+    cover_path: Option<PathBuf>,
+    cover_loader: CoverLoader,
+    graphics: Option<GraphicsProtocol>,
+    /// Where the cover goes when the terminal draws it, set by the last draw
+    cover_area: Cell<Option<Rect>>,
+    /// Cover and place last sent to the terminal
+    drawn_cover: Option<(u64, Rect)>,
+    resume_positions: HashMap<String, u64>,
+    sort_mode: usize,
+    sort_descending: bool,
+    view: View,
+    search: String,
+    hidden: HashSet<String>,
+    undo_stack: Vec<Undo>,
+    show_hidden: bool,
+    finder: Option<Finder>,
+    marked: HashSet<String>,
+    tag_editor: Option<TagEditor>,
+    pattern_preview: Option<PatternPreview>,
+    tree: LibraryTree,
+    directories: DirectoryBrowser,
+    queue: VecDeque<Track>,
+    /// The current track came from the queue, it leaves the playlist once it stops playing
+    playing_queued: bool,
+}
+
+#[derive(PartialEq)]
+enum View {
+    Playlist,
+    Browser,
+    Directories,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(u64),
+    Text(String),
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Tracks,
+    Playlists,
+}
+
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+enum PromptKind {
+    OpenPlaylist,
+    SavePlaylist,
+    NewPlaylist,
+    RenamePlaylist,
+    Search,
+    ConfirmTrash,
+    ConfirmDeletePlaylist,
+}
+
+enum Undo {
+    Hide(String),
+    /// The trashed file and the tracks removed with it, at their former indices
+    Trash {
+        file: TrashedFile,
+        tracks: Vec<(usize, Track)>,
+        library: Vec<(usize, Track)>,
+    },
 }
 
 enum PlaybackState {
@@ -231,14 +673,32 @@ enum PlaybackState {
 
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
-    let (help, recursive, version, music_directory) = parse_args(&args);
-    let tracks = scan_music_files(&music_directory, recursive)?;
+    let (help, recursive, version, mut music_paths) = parse_args(&args);
+    let config = load_config();
+
+    music_paths.extend(
+        config
+            .library_paths
+            .iter()
+            .map(|p| expand_home(p))
+    );
+
+    if music_paths.is_empty() {
+        music_paths.push(env::current_dir()?);
+    }
+
+    let sort_order = config
+        .sort_modes
+        .first()
+        .cloned()
+        .unwrap_or_else(|| "title".to_string());
+    let tracks = scan_music_files(&music_paths, recursive, &sort_order, config.sort_descending)?;
 
     if help {
         println!(
             r#"
 USAGE:
-    sonido [OPTIONS] [PATH]
+    sonido [OPTIONS] [PATH]...
 
 OPTIONS:
     -h, --help       Print this help message
@@ -273,23 +733,42 @@ the Free Software Foundation, either version 3 of the License, or
     }
 
     if tracks.is_empty() {
-        anyhow::bail!("No music files found in {}", music_directory.display());
+        anyhow::bail!(
+            "No music files found in {}",
+            music_paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
     }
 
     enable_raw_mode()?;
 
     let mut stdout = std::io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
+
+    if config.mouse_support {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let config = load_config();
     let tracks_count = tracks.len();
 
     let mut app = App {
+        tree: LibraryTree::new(&tracks),
+        directories: DirectoryBrowser::new(&music_paths),
+        library: tracks.clone(),
         tracks,
         config,
         current_track: 0,
         list_state: ListState::default().with_selected(Some(0)),
+        list_offset: Cell::new(0),
+        list_height: Cell::new(0),
+        list_area: Cell::new(Rect::default()),
+        progress_area: Cell::new(Rect::default()),
+        drag_position: None,
         playback_state: PlaybackState::Stopped,
         position: Duration::ZERO,
         playback_start: None,
@@ -297,23 +776,80 @@ the Free Software Foundation, either version 3 of the License, or
         sink: None,
         _stream: None,
         scroll_state: ScrollbarState::new(tracks_count),
+        prompt: None,
+        message: None,
+        playlists: named_playlists(),
+        playlists_state: ListState::default().with_selected(Some(0)),
+        active_playlist: None,
+        show_playlists: false,
+        focus: Focus::Tracks,
+        chapters: Vec::new(),
+        chapters_path: None,
+        show_lyrics: false,
+        lyrics: None,
+        lyrics_path: None,
+        lyrics_offset: 0,
+        show_visualizer: false,
+        tap: SampleTap::default(),
+        visualizer_mode: VisualizerMode::Spectrum,
+        spectrum: Spectrum::default(),
+        meters: Meters::default(),
+        show_waveform: false,
+        waveforms: Waveforms::new(),
+        waveform_track: None,
+        waveform: None,
+        cover: None,
+        cover_path: None,
+        cover_loader: CoverLoader::new(),
+        graphics: None,
+        cover_area: Cell::new(None),
+        drawn_cover: None,
+        resume_positions: load_resume_positions(),
+        sort_mode: 0,
+        sort_descending: false,
+        view: View::Playlist,
+        search: String::new(),
+        hidden: HashSet::new(),
+        undo_stack: Vec::new(),
+        show_hidden: false,
+        finder: None,
+        marked: HashSet::new(),
+        tag_editor: None,
+        pattern_preview: None,
+        queue: VecDeque::new(),
+        playing_queued: false,
     };
 
+    app.show_playlists = app.config.show_playlists_panel;
+    app.show_lyrics = app.config.show_lyrics_panel;
+    app.show_visualizer = app.config.show_visualizer;
+    app.visualizer_mode = VisualizerMode::parse(&app.config.visualizer_mode);
+    app.show_waveform = app.config.show_waveform;
+    app.graphics = graphics_protocol(&app.config.cover_art);
+
+    if app.config.persist_hidden {
+        app.hidden = load_hidden();
+
+        // Don't start on a hidden track
+        next_track(&mut app, 0);
+    }
+    app.sort_descending = app.config.sort_descending;
+
     let result = run_app(&mut terminal, &mut app);
 
     disable_raw_mode()?;
 
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(terminal.backend_mut(), DisableMouseCapture, LeaveAlternateScreen)?;
     terminal.show_cursor()?;
 
     return result;
 }
 
-fn parse_args(args: &[String]) -> (bool, bool, bool, PathBuf) {
+fn parse_args(args: &[String]) -> (bool, bool, bool, Vec<PathBuf>) {
     let mut help = false;
     let mut recursive = false;
     let mut version = false;
-    let mut music_directory = None;
+    let mut music_paths = Vec::new();
 
     for arg in args.iter().skip(1) {
         match arg.as_str() {
@@ -328,16 +864,20 @@ fn parse_args(args: &[String]) -> (bool, bool, bool, PathBuf) {
             }
             _ if arg.starts_with('-') => {},
             _ => {
-                if music_directory.is_none() {
-                    music_directory = Some(PathBuf::from(arg));
-                }
+                music_paths.push(PathBuf::from(arg));
             }
         }
     }
 
-    let music_directory = music_directory.unwrap_or_else(|| env::current_dir().unwrap());
+    return (help, recursive, version, music_paths);
+}
+
+fn expand_home(path_str: &str) -> PathBuf {
+    if let Some(rest) = path_str.strip_prefix("~/") && let Some(user_dirs) = UserDirs::new() {
+        return user_dirs.home_dir().join(rest);
+    }
 
-    return (help, recursive, version, music_directory);
+    return PathBuf::from(path_str);
 }
 
 fn parse_key(key_str: &str) -> KeyCode {
@@ -387,6 +927,9 @@ fn parse_key(key_str: &str) -> KeyCode {
         "pagedown" | "pgdown" => {
             return KeyCode::PageDown;
         },
+        key if key.len() > 1 && key.starts_with('f') && key[1..].parse::<u8>().is_ok() => {
+            return KeyCode::F(key[1..].parse().unwrap());
+        },
         key if key.len() == 1 => {
             return KeyCode::Char(
                 key
@@ -509,13 +1052,50 @@ fn load_config() -> ConfigSettings {
 
 fn run_app(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app: &mut App) -> Result<()> {
     loop {
+        refresh_chapters(app);
+        refresh_cover(app);
+        refresh_lyrics(app);
+        refresh_waveform(app);
+
+        if app.show_visualizer {
+            let playing = matches!(app.playback_state, PlaybackState::Playing);
+
+            // The oscilloscope reads the tap as it draws
+            match app.visualizer_mode {
+                VisualizerMode::Spectrum => {
+                    app.spectrum.update(&app.tap, playing, app.config.visualizer_bands, app.config.visualizer_falloff);
+                },
+                VisualizerMode::Meters => {
+                    app.meters.update(&app.tap, playing, app.config.visualizer_falloff);
+                },
+                VisualizerMode::Scope => {},
+            }
+        }
+
         terminal.draw(|f| ui(f, app))?;
+        draw_cover(terminal, app)?;
 
         if event::poll(Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+            let event = event::read()?;
+
+            if let Event::Mouse(mouse) = event {
+                if app.config.mouse_support && app.prompt.is_none() && app.finder.is_none() && app.tag_editor.is_none() && app.pattern_preview.is_none() {
+                    handle_mouse(app, mouse);
+                }
+            } else if let Event::Key(key) = event && key.kind == KeyEventKind::Press {
+                if app.prompt.is_some() {
+                    handle_prompt_key(app, key.code);
+                } else if app.finder.is_some() {
+                    handle_finder_key(app, key.code);
+                } else if app.tag_editor.is_some() {
+                    handle_tag_editor_key(app, key.code);
+                } else if app.pattern_preview.is_some() {
+                    handle_pattern_preview_key(app, key.code);
+                } else if !handle_browser_key(app, key.code) && !handle_directory_key(app, key.code) {
                     match key.code {
                         _ if key.code == parse_key(&app.config.quit) => {
+                            remember_position(app);
+
                             return Ok(());
                         },
                         _ if key.code == parse_key(&app.config.toggle_playback) => {
@@ -531,16 +1111,162 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app: &mut
                             seek(app, app.config.seek_step as i64);
                         },
                         _ if key.code == parse_key(&app.config.previous_track) => {
-                            next_track(app, -1);
+                            if app.focus == Focus::Playlists {
+                                move_playlists_selection(app, -1);
+                            } else {
+                                move_cursor(app, -1);
+                            }
                         },
                         _ if key.code == parse_key(&app.config.next_track) => {
-                            next_track(app, 1);
+                            if app.focus == Focus::Playlists {
+                                move_playlists_selection(app, 1);
+                            } else {
+                                move_cursor(app, 1);
+                            }
                         },
                         _ if key.code == parse_key(&app.config.hide_track) => {
-                            hide_track(app, app.current_track);
+                            if let Some(index) = app.list_state.selected() {
+                                hide_track(app, index);
+                            }
+                        },
+                        _ if key.code == parse_key(&app.config.undo) => {
+                            undo(app);
+                        },
+                        _ if key.code == parse_key(&app.config.mark_track) => {
+                            toggle_mark(app);
+                        },
+                        _ if key.code == parse_key(&app.config.edit_tags) => {
+                            open_tag_editor(app);
+                        },
+                        _ if key.code == parse_key(&app.config.tags_from_path) => {
+                            open_pattern_preview(app, PatternKind::TagsFromPath);
+                        },
+                        _ if key.code == parse_key(&app.config.rename_from_tags) => {
+                            open_pattern_preview(app, PatternKind::RenameFromTags);
+                        },
+                        _ if key.code == parse_key(&app.config.trash_track) => {
+                            // The full path, the playing track may not be the one under the cursor
+                            if let Some(track) = app.tracks.get(app.current_track) {
+                                let path = track.path.to_string_lossy().to_string();
+
+                                open_prompt(app, PromptKind::ConfirmTrash, path);
+                            }
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_hidden) => {
+                            toggle_show_hidden(app);
+                        },
+                        _ if key.code == parse_key(&app.config.open_playlist) => {
+                            open_prompt(app, PromptKind::OpenPlaylist, String::new());
+                        },
+                        _ if key.code == parse_key(&app.config.save_playlist) => {
+                            open_prompt(app, PromptKind::SavePlaylist, String::new());
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_playlists) => {
+                            toggle_playlists(app);
+                        },
+                        _ if key.code == parse_key(&app.config.switch_focus) => {
+                            switch_focus(app);
+                        },
+                        _ if key.code == parse_key(&app.config.select) => {
+                            if app.focus == Focus::Playlists {
+                                switch_playlist(app);
+                            } else {
+                                play_selected(app);
+                            }
+                        },
+                        _ if key.code == parse_key(&app.config.new_playlist) => {
+                            open_prompt(app, PromptKind::NewPlaylist, String::new());
+                        },
+                        // Named playlists are only changed from their panel
+                        _ if key.code == parse_key(&app.config.rename_playlist) && playlists_focused(app) => {
+                            if let Some(name) = selected_playlist(app) {
+                                open_prompt(app, PromptKind::RenamePlaylist, name);
+                            }
+                        },
+                        _ if key.code == parse_key(&app.config.delete_playlist) && playlists_focused(app) => {
+                            if let Some(name) = selected_playlist(app) {
+                                open_prompt(app, PromptKind::ConfirmDeletePlaylist, name);
+                            }
+                        },
+                        _ if key.code == parse_key(&app.config.add_to_playlist) && playlists_focused(app) => {
+                            add_to_playlist(app);
+                        },
+                        _ if key.code == parse_key(&app.config.previous_chapter) => {
+                            jump_chapter(app, -1);
+                        },
+                        _ if key.code == parse_key(&app.config.next_chapter) => {
+                            jump_chapter(app, 1);
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_lyrics) => {
+                            app.show_lyrics = !app.show_lyrics;
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_visualizer) => {
+                            app.show_visualizer = !app.show_visualizer;
+                        },
+                        _ if key.code == parse_key(&app.config.cycle_visualizer) => {
+                            app.visualizer_mode = app.visualizer_mode.next();
+                            app.show_visualizer = true;
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_waveform) => {
+                            app.show_waveform = !app.show_waveform;
+                        },
+                        _ if key.code == parse_key(&app.config.lyrics_earlier) => {
+                            shift_lyrics(app, -LYRICS_OFFSET_STEP);
+                        },
+                        _ if key.code == parse_key(&app.config.lyrics_later) => {
+                            shift_lyrics(app, LYRICS_OFFSET_STEP);
+                        },
+                        _ if key.code == parse_key(&app.config.cycle_sort) => {
+                            cycle_sort(app);
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_sort_direction) => {
+                            toggle_sort_direction(app);
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_browser) => {
+                            toggle_browser(app);
+                        },
+                        _ if key.code == parse_key(&app.config.toggle_directories) => {
+                            toggle_directories(app);
+                        },
+                        _ if key.code == parse_key(&app.config.search) => {
+                            set_view(app, View::Playlist);
+
+                            open_prompt(app, PromptKind::Search, app.search.clone());
+                        },
+                        _ if key.code == parse_key(&app.config.clear_search) => {
+                            app.search.clear();
+                        },
+                        _ if key.code == parse_key(&app.config.page_up) => {
+                            move_cursor(app, -page_size(app));
+                        },
+                        _ if key.code == parse_key(&app.config.page_down) => {
+                            move_cursor(app, page_size(app));
+                        },
+                        _ if key.code == parse_key(&app.config.jump_to_top) => {
+                            move_cursor(app, -(app.tracks.len() as i32));
+                        },
+                        _ if key.code == parse_key(&app.config.jump_to_bottom) => {
+                            move_cursor(app, app.tracks.len() as i32);
+                        },
+                        _ if key.code == parse_key(&app.config.jump_to_playing) => {
+                            jump_to_playing(app);
+                        },
+                        _ if key.code == parse_key(&app.config.center_cursor) => {
+                            center_cursor(app);
+                        },
+                        _ if key.code == parse_key(&app.config.find_track) => {
+                            app.finder = Some(Finder::new(&app.library));
                         },
                         _ if key.code == parse_key(&app.config.reload_config) => {
                             app.config = load_config();
+                            app.graphics = graphics_protocol(&app.config.cover_art);
+                            app.cover_path = None;
+
+                            if app.config.mouse_support {
+                                execute!(terminal.backend_mut(), EnableMouseCapture)?;
+                            } else {
+                                execute!(terminal.backend_mut(), DisableMouseCapture)?;
+                            }
                         },
                         _ => {},
                     }
@@ -552,7 +1278,7 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app: &mut
             app.position = start_time.elapsed();
 
             if app.position >= app.tracks[app.current_track].duration {
-                next_track(app, !app.repeat_mode as i32);
+                advance(app);
             }
         }
     }
@@ -563,12 +1289,21 @@ fn ui(f: &mut Frame, app: &App) {
     let show_playlist_title = app.config.show_playlist_title;
     let show_playlist_scrollbar = app.config.show_playlist_scrollbar;
     let show_metadata_title = app.config.show_metadata_title;
-    let show_metadata_panel = app.config.show_metadata_panel;
+    let show_visualizer = app.show_visualizer;
+    let visualizer_position = app.config.visualizer_position.to_lowercase();
+    let visualizer_at_bottom = visualizer_position == "bottom";
+    let visualizer_instead = show_visualizer && visualizer_position == "instead";
+    let show_metadata_panel = app.config.show_metadata_panel && !visualizer_instead;
     let show_progress_title = app.config.show_progress_title;
 
     let app_title_format = app.config.app_title_format.clone().replace("{VERSION}", VERSION);
     let playlist_title_format = app.config.playlist_title_format.clone();
+    let playlists_title_format = app.config.playlists_title_format.clone();
+    let browser_title_format = app.config.browser_title_format.clone();
+    let directories_title_format = app.config.directories_title_format.clone();
     let metadata_title_format = app.config.metadata_title_format.clone();
+    let lyrics_title_format = app.config.lyrics_title_format.clone();
+    let visualizer_title_format = app.config.visualizer_title_format.clone();
     let progress_title_format = app.config.progress_title_format.clone();
 
     let app_title_alignment = parse_alignment(&app.config.app_title_alignment);
@@ -580,14 +1315,14 @@ fn ui(f: &mut Frame, app: &App) {
 
     let app_title_color = parse_color(&app.config.app_title_color);
     let playlist_color = parse_color(&app.config.playlist_color);
+    let marked_color = parse_color(&app.config.marked_color);
     let metadata_color = parse_color(&app.config.metadata_color);
+    let visualizer_color = parse_color(&app.config.visualizer_color);
     let progress_color = parse_color(&app.config.progress_color);
 
     let mut list_state = app.list_state.clone();
     let track = &app.tracks[app.current_track];
-    list_state.select(Some(app.current_track));
-
-    let mut scrollbar_state = ScrollbarState::new(app.tracks.len()).position(app.current_track);
+    let mut scrollbar_state;
 
     let border_set = if rounded_corners {
         border::ROUNDED
@@ -600,23 +1335,90 @@ fn ui(f: &mut Frame, app: &App) {
         .constraints([
             Constraint::Length(show_app_title.into()),
             Constraint::Min(1),
-            Constraint::Length(3),
+            Constraint::Length(if app.show_waveform {
+                3 + app.config.waveform_height
+            } else {
+                3
+            }),
         ])
         .split(f.area());
 
-    let center_layout = if show_metadata_panel {
+    // At the bottom the visualizer takes the full width above the progress bar
+    let main_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(1),
+            Constraint::Percentage(if show_visualizer && visualizer_at_bottom {
+                30
+            } else {
+                0
+            }),
+        ])
+        .split(layout[1]);
+
+    let playlists_layout = if app.show_playlists {
+        Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(20),
+                Constraint::Percentage(80),
+            ])
+            .split(main_layout[0])
+    } else {
+        Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(0),
+                Constraint::Percentage(100),
+            ])
+            .split(main_layout[0])
+    };
+
+    let center_layout = if show_metadata_panel || app.show_lyrics || (show_visualizer && !visualizer_at_bottom) {
         Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
                 Constraint::Percentage(50),
                 Constraint::Percentage(50),
             ])
-            .split(layout[1])
+            .split(playlists_layout[1])
     } else {
         Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(100)])
-            .split(layout[1])
+            .split(playlists_layout[1])
+    };
+
+    let side_panels = [
+        show_metadata_panel,
+        visualizer_instead,
+        app.show_lyrics,
+        show_visualizer && !visualizer_instead && !visualizer_at_bottom,
+    ];
+    let side_count = side_panels.iter().filter(|&&shown| shown).count() as u32;
+
+    // Stacked in this order, sharing the height, the visualizer either in place of the metadata or last
+    let side_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(side_panels.map(|shown| {
+            if shown {
+                return Constraint::Ratio(1, side_count);
+            }
+
+            return Constraint::Length(0);
+        }))
+        .split(if side_count > 0 {
+            center_layout[1]
+        } else {
+            Rect::default()
+        });
+
+    let focused_style = |focus: Focus| {
+        if app.show_playlists && app.focus == focus {
+            return Style::default().add_modifier(Modifier::BOLD);
+        } else {
+            return Style::default();
+        }
     };
 
     let title = Block::default()
@@ -628,11 +1430,64 @@ fn ui(f: &mut Frame, app: &App) {
 
     f.render_widget(title, layout[0]);
 
-    let items: Vec<ListItem> = app
-        .tracks
+    if app.show_playlists {
+        let playlists_items: Vec<ListItem> = std::iter::once("Library".to_string())
+            .chain(app.playlists.iter().cloned())
+            .enumerate()
+            .map(|(i, name)| {
+                let is_active = match &app.active_playlist {
+                    Some(active) => i > 0 && app.playlists[i - 1] == *active,
+                    None => i == 0,
+                };
+
+                let style = if is_active {
+                    Style::default().fg(playlist_color)
+                } else {
+                    Style::default()
+                };
+
+                return ListItem::new(name).style(style);
+            })
+            .collect();
+
+        let playlists_block = if show_playlist_title {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Playlists).fg(playlist_color))
+                .title(playlists_title_format)
+                .title_alignment(playlist_title_alignment)
+        } else {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Playlists).fg(playlist_color))
+        };
+
+        let playlists_list = List::new(playlists_items)
+            .block(playlists_block)
+            .highlight_style(Style::default().bold());
+
+        let mut playlists_state = app.playlists_state.clone();
+
+        f.render_stateful_widget(playlists_list, playlists_layout[0], &mut playlists_state);
+    }
+
+    // With a search active only the matching tracks are listed
+    let visible = visible_tracks(app);
+
+    list_state.select(
+        app
+            .list_state
+            .selected()
+            .and_then(|cursor| visible.iter().position(|&i| i == cursor))
+    );
+    scrollbar_state = ScrollbarState::new(visible.len()).position(list_state.selected().unwrap_or(0));
+
+    let items: Vec<ListItem> = visible
         .iter()
-        .enumerate()
-        .map(|(i, track)| {
+        .map(|&i| {
+            let track = &app.tracks[i];
             let display_name = track
                 .metadata
                 .title
@@ -647,39 +1502,192 @@ fn ui(f: &mut Frame, app: &App) {
                         .to_string()
                 });
             
-            let style = if i == app.current_track {
-                Style::default().fg(playlist_color)
+            let (mut style, indicator) = if i == app.current_track {
+                (Style::default().fg(playlist_color), app.config.playing_indicator.clone())
             } else {
-                Style::default()
+                (Style::default(), " ".repeat(app.config.playing_indicator.chars().count()))
             };
-            
-            return ListItem::new(display_name).style(style);
+
+            // Only listed when showing hidden tracks
+            if app.hidden.contains(&track_key(track)) {
+                style = style.add_modifier(Modifier::DIM | Modifier::CROSSED_OUT);
+            }
+
+            if app.marked.contains(&track_key(track)) {
+                style = style.fg(marked_color);
+            }
+
+            let mut line = highlight_matches(&display_name, &app.search);
+
+            line.spans.insert(0, Span::raw(indicator));
+
+            return ListItem::new(line).style(style);
         })
         .collect();
 
-    let list = if show_playlist_title {
-        List::new(items)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_set(border_set)
-                    .border_style(Style::default().fg(playlist_color))
-                    .title(playlist_title_format)
-                    .title_alignment(playlist_title_alignment),
-            )
-            .highlight_style(Style::default().bold())
+    let mut list_block = if show_playlist_title {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(focused_style(Focus::Tracks).fg(playlist_color))
+            .title(playlist_title_format)
+            .title_alignment(playlist_title_alignment)
     } else {
-        List::new(items)
-            .block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .border_set(border_set)
-                    .border_style(Style::default().fg(playlist_color))
-            )
-            .highlight_style(Style::default().bold())
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(focused_style(Focus::Tracks).fg(playlist_color))
     };
 
-    f.render_stateful_widget(list, center_layout[0], &mut list_state);
+    if !app.search.is_empty() {
+        list_block = list_block.title_bottom(format!(" /{} ({} of {}) ", app.search, visible.len(), app.tracks.len()));
+    }
+
+    let list = List::new(items)
+        .block(list_block)
+        .highlight_style(Style::default().bold());
+
+    if app.view == View::Browser {
+        let rows = app.tree.rows();
+        let playing = &app.tracks[app.current_track];
+
+        let browser_items: Vec<ListItem> = rows
+            .iter()
+            .map(|row| {
+                let marker = if app.tree.is_expanded(*row) {
+                    "▾ "
+                } else {
+                    "▸ "
+                };
+
+                match *row {
+                    TreeRow::Artist(a) => {
+                        return ListItem::new(format!("{}{}", marker, app.tree.artists[a].name));
+                    },
+                    TreeRow::Album(a, b) => {
+                        let album = &app.tree.artists[a].albums[b];
+                        let name = match &album.year {
+                            Some(year) => format!("  {}{} ({})", marker, album.name, year),
+                            None => format!("  {}{}", marker, album.name),
+                        };
+
+                        return ListItem::new(name);
+                    },
+                    TreeRow::Track(a, b, t) => {
+                        let track = &app.library[app.tree.artists[a].albums[b].tracks[t]];
+                        let title = track
+                            .metadata
+                            .title
+                            .clone()
+                            .unwrap_or_else(|| "Unknown".to_string());
+                        let name = match track.metadata.track_number {
+                            Some(number) => format!("      {:02}. {}", number, title),
+                            None => format!("      {}", title),
+                        };
+
+                        let style = if track.path == playing.path && track.start == playing.start {
+                            Style::default().fg(playlist_color)
+                        } else {
+                            Style::default()
+                        };
+
+                        return ListItem::new(name).style(style);
+                    },
+                }
+            })
+            .collect();
+
+        let browser_block = if show_playlist_title {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Tracks).fg(playlist_color))
+                .title(browser_title_format)
+                .title_alignment(playlist_title_alignment)
+        } else {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Tracks).fg(playlist_color))
+        };
+
+        let browser_list = List::new(browser_items)
+            .block(browser_block)
+            .highlight_style(Style::default().bold());
+
+        let mut tree_state = app.tree.state.clone();
+
+        scrollbar_state = ScrollbarState::new(rows.len()).position(tree_state.selected().unwrap_or(0));
+        *tree_state.offset_mut() = app.list_offset.get();
+
+        f.render_stateful_widget(browser_list, center_layout[0], &mut tree_state);
+
+        app.list_offset.set(tree_state.offset());
+    } else if app.view == View::Directories {
+        let playing = &app.tracks[app.current_track];
+
+        let directory_items: Vec<ListItem> = app
+            .directories
+            .entries
+            .iter()
+            .map(|entry| {
+                if entry.is_directory {
+                    return ListItem::new(format!("▸ {}/", entry.name));
+                }
+
+                let style = if entry.path == playing.path {
+                    Style::default().fg(playlist_color)
+                } else {
+                    Style::default()
+                };
+
+                return ListItem::new(format!("  {}", entry.name)).style(style);
+            })
+            .collect();
+
+        let mut directories_block = if show_playlist_title {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Tracks).fg(playlist_color))
+                .title(directories_title_format)
+                .title_alignment(playlist_title_alignment)
+        } else {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(focused_style(Focus::Tracks).fg(playlist_color))
+        };
+
+        // Always show where we are, the list alone doesn't tell
+        if let Some(location) = app.directories.location() {
+            directories_block = directories_block.title_bottom(format!(" {} ", location));
+        }
+
+        let directories_list = List::new(directory_items)
+            .block(directories_block)
+            .highlight_style(Style::default().bold());
+
+        let mut directories_state = app.directories.state.clone();
+
+        scrollbar_state = ScrollbarState::new(app.directories.entries.len())
+            .position(directories_state.selected().unwrap_or(0));
+        *directories_state.offset_mut() = app.list_offset.get();
+
+        f.render_stateful_widget(directories_list, center_layout[0], &mut directories_state);
+
+        app.list_offset.set(directories_state.offset());
+    } else {
+        *list_state.offset_mut() = app.list_offset.get();
+
+        f.render_stateful_widget(list, center_layout[0], &mut list_state);
+
+        app.list_offset.set(list_state.offset());
+    }
+
+    app.list_height.set(center_layout[0].height.saturating_sub(2) as usize);
+    app.list_area.set(center_layout[0]);
+
     let scrollbar = Scrollbar::default()
         .orientation(ScrollbarOrientation::VerticalRight)
         .thumb_symbol("█")
@@ -692,7 +1700,7 @@ fn ui(f: &mut Frame, app: &App) {
         f.render_stateful_widget(
             scrollbar,
             Rect {
-                x: center_layout[0].width.saturating_sub(2),
+                x: center_layout[0].x + center_layout[0].width.saturating_sub(2),
                 y: center_layout[0].y.saturating_add(1),
                 width: 1,
                 height: center_layout[0].height.saturating_sub(2),
@@ -750,12 +1758,48 @@ fn ui(f: &mut Frame, app: &App) {
     }
     
     if let Some(track_num) = metadata.track_number {
+        let track_str = match metadata.track_total {
+            Some(total) => format!("{}/{}", track_num, total),
+            None => track_num.to_string(),
+        };
+
         lines.push(Line::from(vec![
             Span::styled("Track: ", Style::default().fg(metadata_color)),
-            Span::raw(track_num.to_string()),
+            Span::raw(track_str),
+        ]));
+    }
+
+    if let Some(disc_num) = metadata.disc_number {
+        let disc_str = match metadata.disc_total {
+            Some(total) => format!("{}/{}", disc_num, total),
+            None => disc_num.to_string(),
+        };
+
+        lines.push(Line::from(vec![
+            Span::styled("Disc: ", Style::default().fg(metadata_color)),
+            Span::raw(disc_str),
         ]));
     }
 
+    let credits = [
+        ("Album Artist: ", &metadata.album_artist),
+        ("Composer: ", &metadata.composer),
+        ("Conductor: ", &metadata.conductor),
+        ("Lyricist: ", &metadata.lyricist),
+        ("Label: ", &metadata.label),
+        ("BPM: ", &metadata.bpm),
+        ("Comment: ", &metadata.comment),
+    ];
+
+    for (name, value) in credits {
+        if let Some(value) = value {
+            lines.push(Line::from(vec![
+                Span::styled(name, Style::default().fg(metadata_color)),
+                Span::raw(value.as_str()),
+            ]));
+        }
+    }
+
     if let Some(bitrate) = metadata.bitrate {
         lines.push(Line::from(vec![
             Span::styled("Bitrate: ", Style::default().fg(metadata_color)),
@@ -770,8 +1814,15 @@ fn ui(f: &mut Frame, app: &App) {
         ]));
     }
 
-    if let Some(channels) = metadata.channels {
-        let channel_str = match channels {
+    if let Some(bit_depth) = metadata.bit_depth {
+        lines.push(Line::from(vec![
+            Span::styled("Bit Depth: ", Style::default().fg(metadata_color)),
+            Span::raw(format!("{} bit", bit_depth)),
+        ]));
+    }
+
+    if let Some(channels) = metadata.channels {
+        let channel_str = match channels {
             1 => "Mono".to_string(),
             2 => "Stereo".to_string(),
             n => format!("{} channels", n),
@@ -783,6 +1834,34 @@ fn ui(f: &mut Frame, app: &App) {
         ]));
     }
 
+    if !app.chapters.is_empty() {
+        let current = current_chapter(&app.chapters, app.position);
+
+        lines.push(Line::from(vec![
+            Span::styled("Chapters: ", Style::default().fg(metadata_color)),
+            Span::raw(
+                format!(
+                    "{}/{}",
+                    current.map(|i| i + 1).unwrap_or(0),
+                    app.chapters.len()
+                )
+            ),
+        ]));
+
+        for (i, chapter) in app.chapters.iter().enumerate() {
+            let style = if Some(i) == current {
+                Style::default().fg(metadata_color).bold()
+            } else {
+                Style::default()
+            };
+
+            lines.push(Line::from(vec![
+                Span::styled(format!("  {} ", format_duration(chapter.start)), Style::default().fg(metadata_color)),
+                Span::styled(chapter.title.clone(), style),
+            ]));
+        }
+    }
+
     let metadata_block = if show_metadata_title {
         Block::default()
             .borders(Borders::ALL)
@@ -797,222 +1876,2660 @@ fn ui(f: &mut Frame, app: &App) {
             .border_style(Style::default().fg(metadata_color))
     };
 
+    let metadata_area = metadata_block.inner(side_layout[0]);
+    let mut text_area = metadata_area;
+
+    app.cover_area.set(None);
+
+    // The cover takes up to the top half of the panel, the text goes below it
+    if let (true, Some(protocol), Some(cover)) = (show_metadata_panel, app.graphics, &app.cover) {
+        let cover_area = cover.fit(Rect {
+            height: metadata_area.height / 2,
+            ..metadata_area
+        });
+
+        if cover_area.height >= 3 {
+            text_area.y += cover_area.height + 1;
+            text_area.height = text_area.height.saturating_sub(cover_area.height + 1);
+
+            // Pictures drawn by the terminal would cover the popups
+            let popup = app.finder.is_some()
+                || app.tag_editor.is_some()
+                || app.pattern_preview.is_some()
+                || app.prompt.as_ref().is_some_and(|p| !matches!(p.kind, PromptKind::Search));
+
+            if protocol == GraphicsProtocol::HalfBlocks {
+                f.render_widget(Paragraph::new(cover.half_blocks(cover_area)), cover_area);
+            } else if !popup {
+                app.cover_area.set(Some(cover_area));
+            }
+        }
+    }
+
     let metadata_widget = Paragraph::new(lines)
-        .block(metadata_block)
         .wrap(Wrap { trim: true });
 
     if show_metadata_panel {
-        f.render_widget(metadata_widget, center_layout[1]);
+        f.render_widget(metadata_block, side_layout[0]);
+        f.render_widget(metadata_widget, text_area);
+    }
+
+    if app.show_lyrics {
+        let lyrics_block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(Style::default().fg(metadata_color))
+            .title(lyrics_title_format)
+            .title_alignment(metadata_title_alignment);
+
+        let lyrics_block = if app.lyrics_offset != 0 {
+            lyrics_block.title_bottom(Line::from(format!(" {:+.1}s ", app.lyrics_offset as f64 / 1000.0)).right_aligned())
+        } else {
+            lyrics_block
+        };
+
+        let height = lyrics_block.inner(side_layout[2]).height;
+
+        let (lines, scroll) = match &app.lyrics {
+            Some(Lyrics::Synced(lyrics)) => {
+                let current = current_line(lyrics, lyrics_position(app));
+                let lines: Vec<Line> = lyrics
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, text))| {
+                        let style = match current {
+                            Some(current) if i == current => Style::default().fg(metadata_color).bold(),
+                            Some(current) if i < current => Style::default().add_modifier(Modifier::DIM),
+                            _ => Style::default(),
+                        };
+
+                        return Line::styled(text.clone(), style);
+                    })
+                    .collect();
+
+                // Keep the current line in the middle
+                let scroll = current
+                    .unwrap_or(0)
+                    .saturating_sub(height as usize / 2)
+                    .min(lines.len().saturating_sub(height as usize));
+
+                (lines, scroll)
+            },
+            Some(Lyrics::Plain(lyrics)) => (
+                lyrics
+                    .iter()
+                    .map(|text| Line::from(text.clone()))
+                    .collect(),
+                0,
+            ),
+            None => (
+                vec![Line::styled("No lyrics", Style::default().add_modifier(Modifier::DIM))],
+                0,
+            ),
+        };
+
+        let lyrics_widget = Paragraph::new(lines)
+            .block(lyrics_block)
+            .alignment(Alignment::Center)
+            .scroll((scroll as u16, 0));
+
+        f.render_widget(lyrics_widget, side_layout[2]);
+    }
+
+    if show_visualizer {
+        let visualizer_area = if visualizer_at_bottom {
+            main_layout[1]
+        } else if visualizer_instead {
+            side_layout[1]
+        } else {
+            side_layout[3]
+        };
+
+        // The meters tell how far the left channel is ahead of the right
+        let balance = match (app.visualizer_mode, app.meters.balance()) {
+            (VisualizerMode::Meters, Some(balance)) => format!("L−R {:+.1} dB", balance),
+            _ => String::new(),
+        };
+        let visualizer_block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(Style::default().fg(metadata_color))
+            .title(visualizer_title_format)
+            .title_alignment(metadata_title_alignment)
+            .title_bottom(Line::from(balance).right_aligned());
+        let bars_area = visualizer_block.inner(visualizer_area);
+        let playing = matches!(app.playback_state, PlaybackState::Playing);
+        let visualizer_lines = match app.visualizer_mode {
+            VisualizerMode::Spectrum => app.spectrum.lines(bars_area, visualizer_color),
+            VisualizerMode::Meters => app.meters.lines(bars_area, visualizer_color),
+            VisualizerMode::Scope => scope_lines(&app.tap, playing, bars_area, visualizer_color),
+        };
+
+        f.render_widget(visualizer_block, visualizer_area);
+        f.render_widget(Paragraph::new(visualizer_lines), bars_area);
     }
 
-    let progress = app.position.as_secs_f64() / track.duration.as_secs_f64();
+    let position = app.drag_position.unwrap_or(app.position);
+    let progress = position.as_secs_f64() / track.duration.as_secs_f64();
     let progress_text = format!(
         "{} / {}",
-        format_duration(app.position),
+        format_duration(position),
         format_duration(track.duration)
     );
-    let progress_gauge = if show_progress_title {
-        Gauge::default()
+    let message = app
+        .message
+        .as_ref()
+        .filter(|(_, shown_at)| shown_at.elapsed() < MESSAGE_TIMEOUT)
+        .map(|(message, _)| message.clone())
+        .unwrap_or_default();
+    let progress_block = if show_progress_title {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(Style::default().fg(progress_color))
+            .title(progress_title_format)
+            .title_alignment(progress_title_alignment)
+            .title_bottom(Line::from(message).right_aligned())
+    } else {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(Style::default().fg(progress_color))
+            .title_bottom(Line::from(message).right_aligned())
+    };
+    let progress_inner = progress_block.inner(layout[2]);
+
+    // The waveform fills the rows above the gauge, which keeps the last one
+    let gauge_area = Rect {
+        y: progress_inner.bottom().saturating_sub(1),
+        height: progress_inner.height.min(1),
+        ..progress_inner
+    };
+    let progress_gauge = Gauge::default()
+        .gauge_style(Style::default().fg(progress_color))
+        .ratio(progress)
+        .label(progress_text)
+        .use_unicode(true);
+
+    f.render_widget(progress_block, layout[2]);
+    f.render_widget(progress_gauge, gauge_area);
+
+    if let (true, Some(peaks)) = (app.show_waveform, &app.waveform) {
+        let waveform_area = Rect {
+            height: progress_inner.height.saturating_sub(1),
+            ..progress_inner
+        };
+
+        f.render_widget(
+            Paragraph::new(waveform_lines(peaks, waveform_area, progress, progress_color, Color::DarkGray)),
+            waveform_area,
+        );
+    }
+
+    // Clicks on the waveform seek too
+    app.progress_area.set(layout[2]);
+
+    if !app.chapters.is_empty() && !track.duration.is_zero() {
+        let inner_x = layout[2].x + 1;
+        let inner_width = layout[2].width.saturating_sub(2);
+        let buffer = f.buffer_mut();
+
+        for chapter in app.chapters.iter().skip(1) {
+            let ratio = chapter.start.as_secs_f64() / track.duration.as_secs_f64();
+
+            if ratio >= 1.0 {
+                continue;
+            }
+
+            let x = inner_x + (ratio * inner_width as f64) as u16;
+
+            if let Some(cell) = buffer.cell_mut(Position::new(x, gauge_area.y)) {
+                // Don't draw over the time label
+                if !cell.symbol().chars().any(|c| c.is_ascii_graphic()) {
+                    cell.set_symbol("│").set_fg(progress_color);
+                }
+            }
+        }
+    }
+
+    if let Some(editor) = &app.tag_editor {
+        let area = centered_rect(60, editor.fields.len() as u16 + 2, f.area());
+        let title = if editor.targets.len() > 1 {
+            format!("┤ Edit tags of {} tracks ├", editor.targets.len())
+        } else {
+            "┤ Edit tags ├".to_string()
+        };
+
+        let label_width = editor
+            .fields
+            .iter()
+            .map(|f| f.field.label().len())
+            .max()
+            .unwrap_or(0);
+
+        let lines: Vec<Line> = editor
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let label_style = if i == editor.selected {
+                    Style::default().fg(playlist_color).bold()
+                } else {
+                    Style::default().fg(playlist_color)
+                };
+
+                let value = if field.mixed {
+                    Span::styled("(multiple values)", Style::default().add_modifier(Modifier::DIM))
+                } else {
+                    Span::raw(field.value.clone())
+                };
+
+                return Line::from(vec![
+                    Span::styled(format!("{:<width$}  ", field.field.label(), width = label_width), label_style),
+                    value,
+                ]);
+            })
+            .collect();
+
+        let editor_widget = Paragraph::new(lines)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_set(border_set)
-                    .border_style(Style::default().fg(progress_color))
-                    .title(progress_title_format)
-                    .title_alignment(progress_title_alignment),
-            )
-            .gauge_style(Style::default().fg(progress_color))
-            .ratio(progress)
-            .label(progress_text)
-            .use_unicode(true)
-    } else {
-        Gauge::default()
+                    .border_style(Style::default().fg(playlist_color))
+                    .title(title)
+                    .title_bottom(Line::from(" Enter save, Esc cancel ").right_aligned())
+            );
+
+        f.render_widget(Clear, area);
+        f.render_widget(editor_widget, area);
+
+        let field = &editor.fields[editor.selected];
+        let value_width = if field.mixed {
+            0
+        } else {
+            field.value.chars().count() as u16
+        };
+
+        f.set_cursor_position(Position::new(
+            (area.x + 1 + label_width as u16 + 2 + value_width).min(area.right().saturating_sub(2)),
+            area.y + 1 + editor.selected as u16,
+        ));
+    }
+
+    if let Some(preview) = &app.pattern_preview {
+        let area = centered_rect(90, (preview.entries.len() as u16 + 2).max(3), f.area());
+        let title = match preview.kind {
+            PatternKind::TagsFromPath => format!("┤ Tags from {} ├", preview.pattern),
+            PatternKind::RenameFromTags => format!("┤ Rename to {} ├", preview.pattern),
+        };
+
+        let preview_items: Vec<ListItem> = preview
+            .entries
+            .iter()
+            .map(|entry| {
+                let name = entry
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                let line = match &entry.change {
+                    Ok(PatternChange::Tags(values)) => {
+                        let mut spans = vec![Span::raw(name), Span::styled(" → ", Style::default().fg(playlist_color))];
+
+                        for (i, (field, value)) in values.iter().enumerate() {
+                            if i > 0 {
+                                spans.push(Span::raw(", "));
+                            }
+
+                            spans.push(Span::styled(format!("{}: ", field.label()), Style::default().fg(playlist_color)));
+                            spans.push(Span::raw(value.clone()));
+                        }
+
+                        Line::from(spans)
+                    },
+                    Ok(PatternChange::Rename(new_path)) => {
+                        // Only the part of the paths that changes
+                        let root = entry
+                            .path
+                            .ancestors()
+                            .find(|a| new_path.starts_with(a))
+                            .unwrap_or(Path::new(""));
+                        let relative = |path: &Path| {
+                            return path
+                                .strip_prefix(root)
+                                .unwrap_or(path)
+                                .display()
+                                .to_string();
+                        };
+
+                        Line::from(vec![
+                            Span::raw(relative(&entry.path)),
+                            Span::styled(" → ", Style::default().fg(playlist_color)),
+                            Span::raw(relative(new_path)),
+                        ])
+                    },
+                    Err(reason) => Line::from(vec![
+                        Span::raw(name),
+                        Span::raw(format!("  ({})", reason)),
+                    ])
+                        .style(Style::default().add_modifier(Modifier::DIM)),
+                };
+
+                return ListItem::new(line);
+            })
+            .collect();
+
+        let preview_list = List::new(preview_items)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_set(border_set)
-                    .border_style(Style::default().fg(progress_color)),
+                    .border_style(Style::default().fg(playlist_color))
+                    .title(title)
+                    .title_bottom(
+                        Line::from(format!(" {} of {} to change, Enter apply, Esc cancel ", preview.applicable(), preview.entries.len()))
+                            .right_aligned()
+                    )
             )
-            .gauge_style(Style::default().fg(progress_color))
-            .ratio(progress)
-            .label(progress_text)
-            .use_unicode(true)
-    };
-
-    f.render_widget(progress_gauge, layout[2]);
-}
-
-fn scan_music_files(dir: &Path, recursive: bool) -> Result<Vec<Track>> {
-    let mut tracks = Vec::new();
-    let extensions = [
-        "mp3",
-        "aac",
-        "wav",
-        "flac",
-        "alac",
-        "aiff",
-        "aif",
-        "m4a"
-    ];
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
 
-    let walker = if recursive {
-        WalkDir::new(dir).into_iter()
-    } else {
-        WalkDir::new(dir).max_depth(1).into_iter()
-    };
+        let mut preview_state = preview.state.clone();
 
-    for entry in walker.filter_map(|e| e.ok()) {
-        let path = entry.path();
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(preview_list, area, &mut preview_state);
+    }
 
-        if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if extensions.contains(&ext.to_lowercase().as_str()) {
-                    let duration = get_audio_duration(path).unwrap_or(Duration::ZERO);
-                    let metadata = Metadata::from_path(path);
+    if let Some(finder) = &app.finder {
+        let area = centered_rect(80, f.area().height * 3 / 4, f.area());
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(border_set)
+            .border_style(Style::default().fg(playlist_color))
+            .title("┤ Find track ├")
+            .title_bottom(format!(" {} / {} ", finder.results.len(), app.library.len()));
+        let inner = block.inner(area);
 
-                    tracks.push(Track {
-                        path: path.to_path_buf(),
-                        duration,
-                        metadata,
-                    });
-                }
-            }
-        }
-    }
+        let finder_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(0),
+            ])
+            .split(inner);
 
-    tracks.sort_by(
-        |a, b| {
-            let a_title = a
-                .metadata
-                .title
-                .as_deref()
-                .unwrap_or("")
-                .to_lowercase();
-            let b_title = b
-                .metadata
-                .title
-                .as_deref()
-                .unwrap_or("")
-                .to_lowercase();
+        let (rows, mut finder_state) = finder.visible(finder_layout[1].height as usize);
+        let result_items: Vec<ListItem> = rows
+            .iter()
+            .map(|(i, positions)| {
+                let spans: Vec<Span> = finder
+                    .candidate(*i)
+                    .chars()
+                    .enumerate()
+                    .map(|(j, c)| {
+                        if positions.contains(&j) {
+                            return Span::styled(c.to_string(), Style::default().fg(playlist_color).bold());
+                        }
 
-            return a_title.cmp(&b_title);
-        }
-    );
+                        return Span::raw(c.to_string());
+                    })
+                    .collect();
 
-    return Ok(tracks);
-}
+                return ListItem::new(Line::from(spans));
+            })
+            .collect();
 
-fn get_audio_duration(path: &Path) -> Result<Duration> {
-    let file = std::fs::File::open(path)?;
-    let source = Decoder::new(std::io::BufReader::new(file))?;
+        let results_list = List::new(result_items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
 
-    return Ok(
-        source
-            .total_duration()
-            .unwrap_or(Duration::ZERO)
-    );
-}
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+        f.render_widget(Paragraph::new(format!("> {}", finder.query)), finder_layout[0]);
+        f.render_stateful_widget(results_list, finder_layout[1], &mut finder_state);
+        f.set_cursor_position(Position::new(
+            finder_layout[0].x + 2 + (finder.query.chars().count() as u16).min(finder_layout[0].width.saturating_sub(3)),
+            finder_layout[0].y,
+        ));
+    }
 
-fn format_duration(d: Duration) -> String {
-    return format!(
-        "{}:{:02}",
-        d.as_secs() / 60,
-        d.as_secs() % 60
-    );
-}
+    if let Some(prompt) = &app.prompt {
+        let prompt_title = match prompt.kind {
+            PromptKind::OpenPlaylist => "┤ Open playlist ├",
+            PromptKind::SavePlaylist => "┤ Save playlist as ├",
+            PromptKind::NewPlaylist => "┤ New playlist ├",
+            PromptKind::RenamePlaylist => "┤ Rename playlist ├",
+            PromptKind::Search => "┤ Search ├",
+            PromptKind::ConfirmTrash => "┤ Move to trash? (y/n) ├",
+            PromptKind::ConfirmDeletePlaylist => "┤ Delete playlist? (y/n) ├",
+        };
 
-fn toggle_playback(app: &mut App) {
-    match app.playback_state {
-        PlaybackState::Playing => {
-            if let Some(sink) = &app.sink {
-                sink.pause();
-            }
+        // The search box sits over the bottom of the list it filters
+        let area = match prompt.kind {
+            PromptKind::Search => Rect {
+                y: center_layout[0].bottom().saturating_sub(3),
+                height: 3.min(center_layout[0].height),
+                ..center_layout[0]
+            },
+            _ => centered_rect(60, 3, f.area()),
+        };
+        let prompt_widget = Paragraph::new(prompt.input.as_str())
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_set(border_set)
+                    .border_style(Style::default().fg(playlist_color))
+                    .title(prompt_title)
+            );
 
-            app.playback_state = PlaybackState::Paused;
-            app.playback_start = None;
-        },
-        PlaybackState::Paused => {
-            if let Some(sink) = &app.sink {
-                sink.play();
-            }
+        f.render_widget(Clear, area);
+        f.render_widget(prompt_widget, area);
 
-            app.playback_state = PlaybackState::Playing;
-            app.playback_start = Some(Instant::now() - app.position);
-        },
-        PlaybackState::Stopped => {
-            play_track(app);
+        if !matches!(prompt.kind, PromptKind::ConfirmTrash | PromptKind::ConfirmDeletePlaylist) {
+            f.set_cursor_position(Position::new(
+                area.x + 1 + (prompt.input.chars().count() as u16).min(area.width.saturating_sub(3)),
+                area.y + 1,
+            ));
         }
     }
 }
 
-fn toggle_repeat(app: &mut App) {
-    app.repeat_mode = if app.repeat_mode {
-        false
-    } else {
-        true
+fn centered_rect(percent_x: u16, height: u16, area: Rect) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = height.min(area.height);
+
+    return Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
     };
 }
 
-fn seek(app: &mut App, seconds: i64) {
-    let new_pos = app.position.as_secs() as i64 + seconds;
-    let duration = app.tracks[app.current_track].duration.as_secs() as i64;
-    let new_pos = new_pos.clamp(0, duration) as u64;
+fn scan_music_files(paths: &[PathBuf], recursive: bool, sort_order: &str, descending: bool) -> Result<Vec<Track>> {
+    let mut tracks = Vec::new();
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
 
-    app.position = Duration::from_secs(new_pos);
+    let mut playlist_paths = Vec::new();
 
-    if let (Some(sink), PlaybackState::Playing) = (&app.sink, &app.playback_state) {
-        sink.stop();
+    for path in paths {
+        if path.is_file() && is_playlist_file(path) {
+            playlist_paths.push(path);
+
+            continue;
+        }
+
+        let walker = if recursive {
+            WalkDir::new(path).into_iter()
+        } else {
+            WalkDir::new(path).max_depth(1).into_iter()
+        };
+
+        for entry in walker.filter_map(|e| e.ok()) {
+            let path = entry.path();
 
-        if let Ok(file) = std::fs::File::open(&app.tracks[app.current_track].path) {
-            if let Ok(mut source) = Decoder::new(std::io::BufReader::new(file)) {
-                source.try_seek(app.position).ok();
-                sink.append(source);
+            if path.is_file() && (is_music_file(path) || is_cue_file(path)) {
+                let key = path
+                    .canonicalize()
+                    .unwrap_or_else(|_| path.to_path_buf());
 
-                app.playback_start = Some(Instant::now() - app.position);
+                if seen.insert(key) {
+                    files.push(path.to_path_buf());
+                }
             }
         }
-    } else if let Some(playback_start) = app.playback_start {
-        app.playback_start = Some(playback_start);
     }
-}
 
-fn play_track(app: &mut App) {
-    if let Ok((stream, handle)) = OutputStream::try_default() {
-        if let Ok(file) = std::fs::File::open(&app.tracks[app.current_track].path) {
-            if let Ok(source) = Decoder::new(std::io::BufReader::new(file)) {
-                let sink = Sink::try_new(&handle).unwrap();
+    // Files covered by a cue sheet are replaced by the tracks it describes
+    let mut covered = HashSet::new();
 
-                sink.append(source);
-                app.position = Duration::ZERO;
-                app.playback_start = Some(Instant::now());
-                app.sink = Some(sink);
-                app._stream = Some(stream);
-                app.playback_state = PlaybackState::Playing;
+    for path in files.iter().filter(|p| is_cue_file(p)) {
+        for track in load_cue_file(path) {
+            covered.insert(track.path.canonicalize().unwrap_or_else(|_| track.path.clone()));
+            tracks.push(track);
+        }
+    }
 
-                return;
-            }
+    for path in files.iter().filter(|p| is_music_file(p)) {
+        let key = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.clone());
+
+        if covered.contains(&key) {
+            continue;
+        }
+
+        match load_embedded_cue(path) {
+            Some(cue_tracks) => {
+                tracks.extend(cue_tracks);
+            },
+            None => {
+                tracks.push(load_track(path));
+            },
         }
     }
 
-    app.playback_state = PlaybackState::Stopped;
-}
+    sort_tracks(&mut tracks, sort_order, descending);
 
-fn next_track(app: &mut App, direction: i32) {
-    let len = app.tracks.len() as i32;
+    // Playlists keep their own order and go after the scanned tracks
+    let mut playlist_tracks = Vec::new();
 
-    app.current_track = (app.current_track as i32 + direction).rem_euclid(len) as usize;
-    app.list_state.select(Some(app.current_track));
-    app.position = Duration::ZERO;
-    app.playback_start = None;
-    app.scroll_state = ScrollbarState::new(app.tracks.len()).position(app.current_track);
+    for path in playlist_paths {
+        let loaded = load_playlist(path, &tracks)
+            .with_context(|| format!("Failed to load playlist {}", path.display()))?;
 
-    if !matches!(app.playback_state, PlaybackState::Stopped) {
-        play_track(app);
+        playlist_tracks.extend(loaded);
     }
+
+    tracks.extend(playlist_tracks);
+
+    return Ok(tracks);
 }
 
-fn hide_track(app: &mut App, index: usize) {
-    app.tracks.remove(index);
+fn sort_tracks(tracks: &mut [Track], sort_order: &str, descending: bool) {
+    let keys: Vec<String> = sort_order
+        .split(',')
+        .map(|key| key.trim().to_lowercase())
+        .filter(|key| !key.is_empty())
+        .collect();
 
-    next_track(app, 0);
+    let values: Vec<Vec<Option<SortValue>>> = tracks
+        .iter()
+        .map(|track| keys.iter().map(|key| sort_value(track, key)).collect())
+        .collect();
+
+    // Only the first key follows the direction, so albums still play in order
+    let compare = |a: &[Option<SortValue>], b: &[Option<SortValue>]| {
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            let ordering = match (a, b) {
+                // Missing values always go last, whatever the direction
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                _ if i == 0 && descending => a.cmp(b).reverse(),
+                _ => a.cmp(b),
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        return Ordering::Equal;
+    };
+
+    let mut order: Vec<usize> = (0..tracks.len()).collect();
+
+    order.sort_by(|&a, &b| compare(&values[a], &values[b]));
+
+    let sorted: Vec<Track> = order
+        .iter()
+        .map(|&i| tracks[i].clone())
+        .collect();
+
+    tracks.clone_from_slice(&sorted);
+}
+
+fn sort_value(track: &Track, key: &str) -> Option<SortValue> {
+    let metadata = &track.metadata;
+    let text = |value: &Option<String>| {
+        return value
+            .as_ref()
+            .map(|v| SortValue::Text(v.to_lowercase()));
+    };
+
+    match key {
+        "title" => {
+            return text(&metadata.title);
+        },
+        "artist" => {
+            return text(&metadata.artist);
+        },
+        "albumartist" => {
+            // Compilations are grouped under their album artist, not each track's artist
+            return text(&metadata.album_artist).or_else(|| text(&metadata.artist));
+        },
+        "composer" => {
+            return text(&metadata.composer);
+        },
+        "album" => {
+            return text(&metadata.album);
+        },
+        "genre" => {
+            return text(&metadata.genre);
+        },
+        "year" => {
+            return metadata
+                .year
+                .as_ref()
+                .and_then(|y| y.get(..4))
+                .and_then(|y| y.parse().ok())
+                .map(SortValue::Number);
+        },
+        "disc" => {
+            return metadata.disc_number.map(|n| SortValue::Number(n as u64));
+        },
+        "track" => {
+            return metadata.track_number.map(|n| SortValue::Number(n as u64));
+        },
+        "path" => {
+            return Some(SortValue::Text(track.path.to_string_lossy().to_lowercase()));
+        },
+        "duration" => {
+            return Some(SortValue::Number(track.duration.as_millis() as u64));
+        },
+        "added" => {
+            let file_metadata = std::fs::metadata(&track.path).ok()?;
+            let added = file_metadata
+                .created()
+                .or_else(|_| file_metadata.modified())
+                .ok()?;
+
+            return added
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|d| SortValue::Number(d.as_secs()));
+        },
+        _ => {
+            return None;
+        },
+    }
+}
+
+fn cycle_sort(app: &mut App) {
+    if app.config.sort_modes.is_empty() {
+        return;
+    }
+
+    app.sort_mode = (app.sort_mode + 1) % app.config.sort_modes.len();
+
+    apply_sort(app);
+}
+
+fn toggle_sort_direction(app: &mut App) {
+    app.sort_descending = !app.sort_descending;
+
+    apply_sort(app);
+}
+
+fn apply_sort(app: &mut App) {
+    let Some(sort_order) = app.config.sort_modes.get(app.sort_mode).cloned() else {
+        return;
+    };
+
+    if app.tracks.is_empty() {
+        return;
+    }
+
+    let current = app.tracks[app.current_track].clone();
+    let selected = app
+        .list_state
+        .selected()
+        .and_then(|i| app.tracks.get(i))
+        .cloned();
+
+    sort_tracks(&mut app.tracks, &sort_order, app.sort_descending);
+
+    // Keep the same tracks playing and selected, their indices have changed
+    let find = |track: &Track| {
+        return app
+            .tracks
+            .iter()
+            .position(|t| t.path == track.path && t.start == track.start);
+    };
+
+    app.current_track = find(&current).unwrap_or(0);
+
+    let cursor = selected
+        .and_then(|t| find(&t))
+        .unwrap_or(app.current_track);
+
+    app.list_state.select(Some(cursor));
+    app.scroll_state = ScrollbarState::new(app.tracks.len()).position(cursor);
+
+    let direction = if app.sort_descending {
+        "descending"
+    } else {
+        "ascending"
+    };
+
+    show_message(app, format!("Sorted by {} ({})", sort_order.replace(',', ", "), direction));
+}
+
+fn is_music_file(path: &Path) -> bool {
+    let extensions = [
+        "mp3",
+        "aac",
+        "wav",
+        "flac",
+        "alac",
+        "aiff",
+        "aif",
+        "m4a",
+        "m4b"
+    ];
+
+    return path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()));
+}
+
+fn load_track(path: &Path) -> Track {
+    let duration = get_audio_duration(path).unwrap_or(Duration::ZERO);
+    let metadata = Metadata::from_path(path);
+
+    return Track {
+        path: path.to_path_buf(),
+        start: Duration::ZERO,
+        end: None,
+        duration,
+        metadata,
+    };
+}
+
+fn get_audio_duration(path: &Path) -> Result<Duration> {
+    let file = std::fs::File::open(path)?;
+    let source = Decoder::new(std::io::BufReader::new(file))?;
+
+    return Ok(
+        source
+            .total_duration()
+            .unwrap_or(Duration::ZERO)
+    );
+}
+
+fn format_duration(d: Duration) -> String {
+    return format!(
+        "{}:{:02}",
+        d.as_secs() / 60,
+        d.as_secs() % 60
+    );
+}
+
+fn toggle_playback(app: &mut App) {
+    match app.playback_state {
+        PlaybackState::Playing => {
+            if let Some(sink) = &app.sink {
+                sink.pause();
+            }
+
+            app.playback_state = PlaybackState::Paused;
+            app.playback_start = None;
+
+            remember_position(app);
+        },
+        PlaybackState::Paused => {
+            if let Some(sink) = &app.sink {
+                sink.play();
+            }
+
+            app.playback_state = PlaybackState::Playing;
+            app.playback_start = Some(Instant::now() - app.position);
+        },
+        PlaybackState::Stopped => {
+            play_track(app);
+        }
+    }
+}
+
+fn toggle_repeat(app: &mut App) {
+    app.repeat_mode = if app.repeat_mode {
+        false
+    } else {
+        true
+    };
+}
+
+fn seek(app: &mut App, seconds: i64) {
+    let new_pos = app.position.as_secs() as i64 + seconds;
+
+    seek_to(app, Duration::from_secs(new_pos.max(0) as u64));
+}
+
+fn seek_to(app: &mut App, position: Duration) {
+    app.position = position.min(app.tracks[app.current_track].duration);
+
+    if let (Some(sink), PlaybackState::Playing) = (&app.sink, &app.playback_state) {
+        sink.stop();
+
+        if let Some(source) = open_track_source(&app.tracks[app.current_track], app.position, &app.tap) {
+            sink.append(source);
+
+            app.playback_start = Some(Instant::now() - app.position);
+        }
+    } else if let Some(playback_start) = app.playback_start {
+        app.playback_start = Some(playback_start);
+    }
+}
+
+fn play_track(app: &mut App) {
+    if let Ok((stream, handle)) = OutputStream::try_default() {
+        let position = resume_position(app);
+
+        if let Some(source) = open_track_source(&app.tracks[app.current_track], position, &app.tap) {
+            let sink = Sink::try_new(&handle).unwrap();
+
+            sink.append(source);
+            app.position = position;
+            app.playback_start = Some(Instant::now() - position);
+            app.sink = Some(sink);
+            app._stream = Some(stream);
+            app.playback_state = PlaybackState::Playing;
+
+            return;
+        }
+    }
+
+    app.playback_state = PlaybackState::Stopped;
+}
+
+fn open_track_source(track: &Track, position: Duration, tap: &SampleTap) -> Option<Box<dyn Source<Item = i16> + Send>> {
+    let file = std::fs::File::open(&track.path).ok()?;
+    let mut source = Decoder::new(std::io::BufReader::new(file)).ok()?;
+    let offset = track.start + position;
+
+    // Not every decoder can seek (e.g. FLAC), skipping samples works for all of them
+    let source: Box<dyn Source<Item = i16> + Send> = if offset.is_zero() || source.try_seek(offset).is_ok() {
+        Box::new(source)
+    } else {
+        Box::new(source.skip_duration(offset))
+    };
+
+    match track.end {
+        Some(end) => {
+            return Some(Box::new(tap.wrap(source.take_duration(end.saturating_sub(offset)))));
+        },
+        None => {
+            return Some(Box::new(tap.wrap(source)));
+        },
+    }
+}
+
+fn next_track(app: &mut App, direction: i32) {
+    let len = app.tracks.len() as i32;
+    let following = app.list_state.selected() == Some(app.current_track);
+
+    remember_position(app);
+
+    let mut index = (app.current_track as i32 + direction).rem_euclid(len) as usize;
+
+    // Skip hidden tracks and tracks filtered out by the search
+    let step = if direction < 0 {
+        -1
+    } else {
+        1
+    };
+
+    if let Some(playable) = (0..len)
+        .map(|n| (index as i32 + n * step).rem_euclid(len) as usize)
+        .find(|&i| is_playable(app, i))
+    {
+        index = playable;
+    }
+
+    app.current_track = leave_queued_track(app, index);
+
+    // The cursor only follows playback when it was on the playing track
+    if following {
+        app.list_state.select(Some(app.current_track));
+        app.scroll_state = ScrollbarState::new(app.tracks.len()).position(app.current_track);
+    }
+
+    app.position = Duration::ZERO;
+    app.playback_start = None;
+
+    if !matches!(app.playback_state, PlaybackState::Stopped) {
+        play_track(app);
+    }
+}
+
+fn hide_track(app: &mut App, index: usize) {
+    let Some(track) = app.tracks.get(index) else {
+        return;
+    };
+
+    let key = track_key(track);
+    let title = track
+        .metadata
+        .title
+        .clone()
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    if !app.hidden.insert(key.clone()) {
+        return;
+    }
+
+    app.undo_stack.push(Undo::Hide(key));
+
+    save_hidden(app);
+
+    if index == app.current_track {
+        next_track(app, 1);
+    }
+
+    move_cursor(app, 0);
+    show_message(app, format!("Hid \"{}\", press {} to undo", title, app.config.undo));
+}
+
+fn undo(app: &mut App) {
+    match app.undo_stack.pop() {
+        Some(Undo::Hide(key)) => {
+            undo_hide(app, key);
+        },
+        Some(Undo::Trash { file, tracks, library }) => {
+            undo_trash(app, file, tracks, library);
+        },
+        None => {
+            show_message(app, "Nothing to undo".to_string());
+        },
+    }
+}
+
+fn undo_hide(app: &mut App, key: String) {
+    app.hidden.remove(&key);
+
+    save_hidden(app);
+
+    if let Some(index) = app.tracks.iter().position(|t| track_key(t) == key) {
+        app.list_state.select(Some(index));
+
+        let title = app.tracks[index]
+            .metadata
+            .title
+            .clone()
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        show_message(app, format!("Restored \"{}\"", title));
+    }
+}
+
+fn toggle_show_hidden(app: &mut App) {
+    app.show_hidden = !app.show_hidden;
+
+    move_cursor(app, 0);
+
+    if app.show_hidden {
+        show_message(app, format!("Showing {} hidden track(s)", app.hidden.len()));
+    } else {
+        show_message(app, "Hiding hidden tracks".to_string());
+    }
+}
+
+/// Identifies a track across launches, cue sheet tracks share a file so their start is added
+fn track_key(track: &Track) -> String {
+    let path = track.path.to_string_lossy().to_string();
+
+    if track.start.is_zero() {
+        return path;
+    }
+
+    return format!("{}#{}", path, track.start.as_millis());
+}
+
+fn load_hidden() -> HashSet<String> {
+    let Some(path) = data_directory().map(|d| d.join("hidden.txt")) else {
+        return HashSet::new();
+    };
+
+    return std::fs::read_to_string(path)
+        .map(|contents| {
+            contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+fn save_hidden(app: &App) {
+    if !app.config.persist_hidden {
+        return;
+    }
+
+    let Some(directory) = data_directory() else {
+        return;
+    };
+
+    let mut keys: Vec<&String> = app.hidden.iter().collect();
+
+    keys.sort();
+
+    let contents: String = keys
+        .iter()
+        .map(|key| format!("{}\n", key))
+        .collect();
+
+    let _ = std::fs::create_dir_all(&directory);
+    let _ = std::fs::write(directory.join("hidden.txt"), contents);
+}
+
+/// Moves the cursor without touching playback, skipping tracks hidden by the search
+fn move_cursor(app: &mut App, direction: i32) {
+    let visible = visible_tracks(app);
+
+    if visible.is_empty() {
+        return;
+    }
+
+    let cursor = app.list_state.selected().unwrap_or(app.current_track);
+
+    let row = match visible.iter().position(|&i| i == cursor) {
+        Some(row) => (row as i32 + direction).clamp(0, visible.len() as i32 - 1) as usize,
+        // The cursor is on a filtered out track, go to the nearest match after it
+        None => visible
+            .iter()
+            .position(|&i| i > cursor)
+            .unwrap_or(visible.len() - 1),
+    };
+
+    app.list_state.select(Some(visible[row]));
+    app.scroll_state = ScrollbarState::new(visible.len()).position(row);
+}
+
+fn page_size(app: &App) -> i32 {
+    return app.list_height.get().max(1) as i32;
+}
+
+fn jump_to_playing(app: &mut App) {
+    // The playing track may be hidden by the search
+    if !app.search.is_empty() && !track_matches(&app.tracks[app.current_track], &app.search) {
+        app.search.clear();
+    }
+
+    set_view(app, View::Playlist);
+    app.list_state.select(Some(app.current_track));
+
+    center_cursor(app);
+}
+
+/// Scrolls the track list so the cursor is in the middle
+fn center_cursor(app: &mut App) {
+    let Some(cursor) = app.list_state.selected() else {
+        return;
+    };
+
+    let row = visible_tracks(app)
+        .iter()
+        .position(|&i| i == cursor)
+        .unwrap_or(0);
+
+    app.list_offset.set(row.saturating_sub(app.list_height.get() / 2));
+}
+
+fn play_selected(app: &mut App) {
+    let Some(index) = app.list_state.selected().filter(|&i| i < app.tracks.len()) else {
+        return;
+    };
+
+    remember_position(app);
+
+    app.current_track = leave_queued_track(app, index);
+    app.position = Duration::ZERO;
+    app.playback_start = None;
+
+    play_track(app);
+}
+
+/// Takes a track played from the queue back out of the playlist when moving on to `index`, returns where that ends up
+fn leave_queued_track(app: &mut App, index: usize) -> usize {
+    let queued = app.current_track;
+
+    if !app.playing_queued || index == queued || queued >= app.tracks.len() {
+        return index;
+    }
+
+    app.playing_queued = false;
+    app.tracks.remove(queued);
+
+    if let Some(cursor) = app.list_state.selected().filter(|&i| i > queued) {
+        app.list_state.select(Some(cursor - 1));
+    }
+
+    if index > queued {
+        return index - 1;
+    }
+
+    return index;
+}
+
+fn open_prompt(app: &mut App, kind: PromptKind, input: String) {
+    app.prompt = Some(Prompt {
+        kind,
+        input,
+    });
+}
+
+fn handle_prompt_key(app: &mut App, key_code: KeyCode) {
+    let Some(prompt) = &mut app.prompt else {
+        return;
+    };
+
+    // Confirmations only take yes or no
+    if matches!(prompt.kind, PromptKind::ConfirmTrash | PromptKind::ConfirmDeletePlaylist) {
+        let Some(prompt) = app.prompt.take() else {
+            return;
+        };
+
+        if !matches!(key_code, KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter) {
+            return;
+        }
+
+        if matches!(prompt.kind, PromptKind::ConfirmTrash) {
+            trash_track(app, prompt.input);
+        } else {
+            delete_playlist(app, prompt.input);
+        }
+
+        return;
+    }
+
+    let searching = matches!(prompt.kind, PromptKind::Search);
+
+    match key_code {
+        KeyCode::Esc => {
+            app.prompt = None;
+
+            // Cancelling the search also drops the filter
+            if searching {
+                app.search.clear();
+            }
+        },
+        KeyCode::Enter => {
+            if let Some(prompt) = app.prompt.take() {
+                submit_prompt(app, prompt);
+            }
+        },
+        KeyCode::Backspace => {
+            prompt.input.pop();
+        },
+        KeyCode::Char(c) => {
+            prompt.input.push(c);
+        },
+        KeyCode::Up if searching => {
+            move_cursor(app, -1);
+        },
+        KeyCode::Down if searching => {
+            move_cursor(app, 1);
+        },
+        _ => {},
+    }
+
+    // The list is filtered as you type, keeping the cursor on a match
+    if let Some(Prompt { kind: PromptKind::Search, input }) = &app.prompt {
+        app.search = input.clone();
+
+        move_cursor(app, 0);
+    }
+}
+
+fn submit_prompt(app: &mut App, prompt: Prompt) {
+    let input = prompt.input.trim();
+
+    if input.is_empty() {
+        return;
+    }
+
+    match prompt.kind {
+        PromptKind::ConfirmTrash | PromptKind::ConfirmDeletePlaylist => {},
+        PromptKind::Search => {
+            let count = visible_tracks(app).len();
+
+            show_message(app, format!("{} track(s) matching \"{}\"", count, input));
+        },
+        PromptKind::OpenPlaylist => {
+            let path = expand_home(input);
+
+            match load_playlist(&path, &app.library) {
+                Ok(tracks) if tracks.is_empty() => {
+                    show_message(app, format!("No music files found in {}", path.display()));
+                },
+                Ok(tracks) => {
+                    let count = tracks.len();
+
+                    set_tracks(app, tracks);
+                    show_message(app, format!("Loaded {} tracks from {}", count, path.display()));
+                },
+                Err(e) => {
+                    show_message(app, format!("Failed to load {}: {}", path.display(), e));
+                },
+            }
+        },
+        PromptKind::SavePlaylist => {
+            let mut path = expand_home(input);
+
+            if path.extension().is_none() {
+                path.set_extension("m3u8");
+            }
+
+            // Hidden tracks and the one playing from the queue are left out
+            let tracks: Vec<Track> = app
+                .tracks
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !app.playing_queued || *i != app.current_track)
+                .filter(|(_, t)| !app.hidden.contains(&track_key(t)))
+                .map(|(_, t)| t.clone())
+                .collect();
+
+            match save_playlist(&path, &tracks, app.config.playlist_relative_paths) {
+                Ok(()) => {
+                    show_message(app, format!("Saved {} tracks to {}", tracks.len(), path.display()));
+                },
+                Err(e) => {
+                    show_message(app, format!("Failed to save {}: {}", path.display(), e));
+                },
+            }
+        },
+        PromptKind::NewPlaylist => {
+            let result = named_playlist_path(input)
+                .and_then(|path| {
+                    if path.exists() {
+                        anyhow::bail!("Playlist \"{}\" already exists", input);
+                    }
+
+                    return save_playlist(&path, &[], false);
+                });
+
+            match result {
+                Ok(()) => {
+                    refresh_playlists(app, Some(input));
+                    show_message(app, format!("Created playlist \"{}\"", input));
+                },
+                Err(e) => {
+                    show_message(app, format!("Failed to create playlist: {}", e));
+                },
+            }
+        },
+        PromptKind::RenamePlaylist => {
+            let Some(old_name) = selected_playlist(app) else {
+                return;
+            };
+
+            let result = named_playlist_path(&old_name)
+                .and_then(|old_path| {
+                    let new_path = named_playlist_path(input)?;
+
+                    if new_path.exists() {
+                        anyhow::bail!("Playlist \"{}\" already exists", input);
+                    }
+
+                    std::fs::rename(old_path, new_path)?;
+
+                    return Ok(());
+                });
+
+            match result {
+                Ok(()) => {
+                    if app.active_playlist.as_deref() == Some(old_name.as_str()) {
+                        app.active_playlist = Some(input.to_string());
+                    }
+
+                    refresh_playlists(app, Some(input));
+                    show_message(app, format!("Renamed playlist \"{}\" to \"{}\"", old_name, input));
+                },
+                Err(e) => {
+                    show_message(app, format!("Failed to rename playlist: {}", e));
+                },
+            }
+        },
+    }
+}
+
+fn set_tracks(app: &mut App, tracks: Vec<Track>) {
+    remember_position(app);
+
+    app.tracks = tracks;
+    app.playing_queued = false;
+    app.position = Duration::ZERO;
+    app.current_track = 0;
+    app.list_state.select(Some(0));
+    app.search.clear();
+
+    // Trashed tracks are restored at their indices, which mean nothing in another playlist
+    app.undo_stack.retain(|undo| !matches!(undo, Undo::Trash { .. }));
+
+    next_track(app, 0);
+}
+
+fn show_message(app: &mut App, message: String) {
+    app.message = Some((message, Instant::now()));
+}
+
+fn data_directory() -> Option<PathBuf> {
+    return ProjectDirs::from("", "", "sonido").map(|d| d.data_dir().to_path_buf());
+}
+
+fn toggle_playlists(app: &mut App) {
+    app.show_playlists = !app.show_playlists;
+
+    if app.show_playlists {
+        refresh_playlists(app, app.active_playlist.clone().as_deref());
+    } else {
+        app.focus = Focus::Tracks;
+    }
+}
+
+fn switch_focus(app: &mut App) {
+    app.focus = match app.focus {
+        Focus::Tracks if app.show_playlists => Focus::Playlists,
+        _ => Focus::Tracks,
+    };
+}
+
+fn refresh_playlists(app: &mut App, select: Option<&str>) {
+    app.playlists = named_playlists();
+
+    // Index 0 is the library, named playlists follow it
+    let index = select
+        .and_then(|name| app.playlists.iter().position(|p| p == name))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    app.playlists_state.select(Some(index));
+}
+
+fn move_playlists_selection(app: &mut App, direction: i32) {
+    let len = app.playlists.len() as i32 + 1;
+    let selected = app.playlists_state.selected().unwrap_or(0) as i32;
+
+    app.playlists_state.select(Some((selected + direction).rem_euclid(len) as usize));
+}
+
+fn playlists_focused(app: &App) -> bool {
+    return app.show_playlists && app.focus == Focus::Playlists;
+}
+
+fn selected_playlist(app: &App) -> Option<String> {
+    let index = app.playlists_state.selected()?;
+
+    return app.playlists.get(index.checked_sub(1)?).cloned();
+}
+
+fn switch_playlist(app: &mut App) {
+    let Some(name) = selected_playlist(app) else {
+        app.active_playlist = None;
+        set_tracks(app, app.library.clone());
+        show_message(app, "Switched to library".to_string());
+
+        return;
+    };
+
+    let tracks = named_playlist_path(&name)
+        .and_then(|path| load_playlist(&path, &app.library));
+
+    match tracks {
+        Ok(tracks) if tracks.is_empty() => {
+            show_message(app, format!("Playlist \"{}\" is empty", name));
+        },
+        Ok(tracks) => {
+            set_tracks(app, tracks);
+            show_message(app, format!("Switched to playlist \"{}\"", name));
+            app.active_playlist = Some(name);
+        },
+        Err(e) => {
+            show_message(app, format!("Failed to load playlist \"{}\": {}", name, e));
+        },
+    }
+}
+
+fn delete_playlist(app: &mut App, name: String) {
+    let result = named_playlist_path(&name)
+        .and_then(|path| Ok(std::fs::remove_file(path)?));
+
+    match result {
+        Ok(()) => {
+            if app.active_playlist.as_deref() == Some(name.as_str()) {
+                app.active_playlist = None;
+                set_tracks(app, app.library.clone());
+            }
+
+            refresh_playlists(app, None);
+            show_message(app, format!("Deleted playlist \"{}\"", name));
+        },
+        Err(e) => {
+            show_message(app, format!("Failed to delete playlist \"{}\": {}", name, e));
+        },
+    }
+}
+
+fn add_to_playlist(app: &mut App) {
+    let Some(name) = selected_playlist(app) else {
+        show_message(app, "Select a playlist in the playlists panel first".to_string());
+
+        return;
+    };
+
+    let index = app
+        .list_state
+        .selected()
+        .filter(|&i| i < app.tracks.len())
+        .unwrap_or(app.current_track);
+    let track = app.tracks[index].clone();
+    let result = named_playlist_path(&name).and_then(|path| append_to_playlist(&path, &track));
+
+    match result {
+        Ok(()) => {
+            let title = track
+                .metadata
+                .title
+                .clone()
+                .unwrap_or_default();
+
+            if app.active_playlist.as_deref() == Some(name.as_str()) {
+                app.tracks.push(track);
+            }
+
+            show_message(app, format!("Added \"{}\" to playlist \"{}\"", title, name));
+        },
+        Err(e) => {
+            show_message(app, format!("Failed to add to playlist \"{}\": {}", name, e));
+        },
+    }
+}
+
+fn refresh_chapters(app: &mut App) {
+    let track = &app.tracks[app.current_track];
+
+    if app.chapters_path.as_ref() == Some(&track.path) {
+        return;
+    }
+
+    // Chapters of a file split by a cue sheet don't line up with its tracks
+    app.chapters = if track.start.is_zero() && track.end.is_none() {
+        read_chapters(&track.path)
+    } else {
+        Vec::new()
+    };
+    app.chapters_path = Some(track.path.clone());
+}
+
+fn refresh_lyrics(app: &mut App) {
+    let track = &app.tracks[app.current_track];
+
+    if !app.show_lyrics || app.lyrics_path.as_ref() == Some(&track.path) {
+        return;
+    }
+
+    app.lyrics = load_lyrics(&track.path);
+    app.lyrics_path = Some(track.path.clone());
+    app.lyrics_offset = 0;
+}
+
+/// Asks for the current track's peaks when it changes, analysis runs in the background
+fn refresh_waveform(app: &mut App) {
+    if !app.show_waveform {
+        app.waveform = None;
+        app.waveform_track = None;
+
+        return;
+    }
+
+    let track = &app.tracks[app.current_track];
+
+    if app.waveform_track.as_ref().is_none_or(|(path, start)| *path != track.path || *start != track.start) {
+        app.waveforms.request(track);
+        app.waveform_track = Some((track.path.clone(), track.start));
+    }
+
+    app.waveform = app.waveforms.current();
+}
+
+/// Where playback is in the file the lyrics belong to, with the offset applied
+fn lyrics_position(app: &App) -> Duration {
+    let position = app.tracks[app.current_track].start + app.position;
+
+    if app.lyrics_offset >= 0 {
+        return position.saturating_sub(Duration::from_millis(app.lyrics_offset as u64));
+    }
+
+    return position + Duration::from_millis(app.lyrics_offset.unsigned_abs());
+}
+
+fn shift_lyrics(app: &mut App, milliseconds: i64) {
+    if !matches!(app.lyrics, Some(Lyrics::Synced(_))) {
+        return;
+    }
+
+    app.lyrics_offset += milliseconds;
+}
+
+fn refresh_cover(app: &mut App) {
+    let track = &app.tracks[app.current_track];
+
+    if app.cover_path.as_ref() != Some(&track.path) {
+        if app.graphics.is_some() {
+            app.cover_loader.request(&track.path);
+        }
+
+        app.cover = None;
+        app.cover_path = Some(track.path.clone());
+    }
+
+    // Covers of tracks skipped while they loaded are dropped
+    while let Some((path, cover)) = app.cover_loader.loaded() {
+        if app.cover_path.as_ref() == Some(&path) {
+            app.cover = cover;
+        }
+    }
+}
+
+/// Sends the cover to the terminal when it or its place changed since the last draw
+fn draw_cover(terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>, app: &mut App) -> Result<()> {
+    let target = app
+        .cover
+        .as_ref()
+        .zip(app.cover_area.get())
+        .map(|(cover, area)| (cover.id, area));
+
+    if target == app.drawn_cover {
+        return Ok(());
+    }
+
+    if let Some(protocol) = app.graphics.filter(|_| app.drawn_cover.is_some()) {
+        if protocol == GraphicsProtocol::Kitty {
+            queue!(terminal.backend_mut(), Print(clear_sequence(protocol)))?;
+        } else {
+            // Sixel and iTerm pictures are part of the screen, only redrawing everything removes them
+            terminal.clear()?;
+            terminal.draw(|f| ui(f, app))?;
+        }
+    }
+
+    if let (Some(protocol), Some(cover), Some(area)) = (app.graphics, &app.cover, app.cover_area.get()) {
+        queue!(
+            terminal.backend_mut(),
+            MoveTo(area.x, area.y),
+            Print(cover.escape_sequence(protocol, area)),
+        )?;
+    }
+
+    std::io::Write::flush(terminal.backend_mut())?;
+
+    app.drawn_cover = target;
+
+    return Ok(());
+}
+
+fn jump_chapter(app: &mut App, direction: i32) {
+    if app.chapters.is_empty() {
+        return;
+    }
+
+    let current = current_chapter(&app.chapters, app.position);
+
+    let target = match (current, direction) {
+        (None, _) => 0,
+        // Go back to the start of the current chapter first, like most players do
+        (Some(i), d) if d < 0 && app.position - app.chapters[i].start > Duration::from_secs(3) => i,
+        (Some(i), d) if d < 0 => i.saturating_sub(1),
+        (Some(i), _) => i + 1,
+    };
+
+    match app.chapters.get(target) {
+        Some(chapter) => {
+            let start = chapter.start;
+
+            seek_to(app, start);
+        },
+        None => {
+            next_track(app, 1);
+        },
+    }
+}
+
+fn load_resume_positions() -> HashMap<String, u64> {
+    let Some(path) = data_directory().map(|d| d.join("resume.toml")) else {
+        return HashMap::new();
+    };
+
+    return std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default();
+}
+
+fn save_resume_positions(app: &App) {
+    let Some(directory) = data_directory() else {
+        return;
+    };
+
+    if let Ok(contents) = toml::to_string(&app.resume_positions) {
+        let _ = std::fs::create_dir_all(&directory);
+        let _ = std::fs::write(directory.join("resume.toml"), contents);
+    }
+}
+
+fn is_resumable(app: &App, track: &Track) -> bool {
+    return app.config.resume_min_duration > 0
+        && track.duration.as_secs() >= app.config.resume_min_duration
+        && track.start.is_zero()
+        && track.end.is_none();
+}
+
+fn resume_position(app: &App) -> Duration {
+    let track = &app.tracks[app.current_track];
+
+    if !is_resumable(app, track) {
+        return Duration::ZERO;
+    }
+
+    return app
+        .resume_positions
+        .get(&track.path.to_string_lossy().to_string())
+        .map(|secs| Duration::from_secs(*secs))
+        .filter(|position| *position < track.duration)
+        .unwrap_or(Duration::ZERO);
+}
+
+fn remember_position(app: &mut App) {
+    let Some(track) = app.tracks.get(app.current_track) else {
+        return;
+    };
+
+    if !is_resumable(app, track) || app.position.is_zero() {
+        return;
+    }
+
+    let key = track.path.to_string_lossy().to_string();
+
+    // A finished book starts over next time
+    if app.position + Duration::from_secs(10) >= track.duration {
+        app.resume_positions.remove(&key);
+    } else {
+        app.resume_positions.insert(key, app.position.as_secs());
+    }
+
+    save_resume_positions(app);
+}
+
+fn advance(app: &mut App) {
+    if app.repeat_mode {
+        next_track(app, 0);
+    } else if let Some(track) = app.queue.pop_front() {
+        // Queued tracks are played next by slotting them in after the current one, until they're done
+        let following = app.list_state.selected() == Some(app.current_track);
+
+        app.tracks.insert(app.current_track + 1, track);
+
+        if let Some(cursor) = app.list_state.selected().filter(|&i| i > app.current_track) {
+            app.list_state.select(Some(cursor + 1));
+        }
+
+        remember_position(app);
+
+        // Played even when hidden or filtered out, it was asked for
+        app.current_track = leave_queued_track(app, app.current_track + 1);
+        app.playing_queued = true;
+
+        if following {
+            app.list_state.select(Some(app.current_track));
+            app.scroll_state = ScrollbarState::new(app.tracks.len()).position(app.current_track);
+        }
+
+        app.position = Duration::ZERO;
+        app.playback_start = None;
+
+        play_track(app);
+    } else {
+        next_track(app, 1);
+    }
+}
+
+fn toggle_browser(app: &mut App) {
+    let view = match app.view {
+        View::Browser => View::Playlist,
+        _ => View::Browser,
+    };
+
+    set_view(app, view);
+}
+
+fn set_view(app: &mut App, view: View) {
+    app.view = view;
+    app.focus = Focus::Tracks;
+
+    // Each view scrolls on its own, start from the top and let the cursor pull it down
+    app.list_offset.set(0);
+}
+
+fn handle_browser_key(app: &mut App, key_code: KeyCode) -> bool {
+    if app.view != View::Browser || app.focus != Focus::Tracks {
+        return false;
+    }
+
+    match key_code {
+        _ if key_code == parse_key(&app.config.previous_track) => {
+            app.tree.move_selection(-1);
+        },
+        _ if key_code == parse_key(&app.config.next_track) => {
+            app.tree.move_selection(1);
+        },
+        _ if key_code == parse_key(&app.config.page_up) => {
+            app.tree.move_selection(-page_size(app));
+        },
+        _ if key_code == parse_key(&app.config.page_down) => {
+            app.tree.move_selection(page_size(app));
+        },
+        _ if key_code == parse_key(&app.config.jump_to_top) => {
+            app.tree.move_selection(i32::MIN / 2);
+        },
+        _ if key_code == parse_key(&app.config.jump_to_bottom) => {
+            app.tree.move_selection(i32::MAX / 2);
+        },
+        _ if key_code == parse_key(&app.config.browser_expand) => {
+            app.tree.expand();
+        },
+        _ if key_code == parse_key(&app.config.browser_collapse) => {
+            app.tree.collapse();
+        },
+        _ if key_code == parse_key(&app.config.select) => {
+            play_tree_row(app);
+        },
+        _ if key_code == parse_key(&app.config.enqueue) => {
+            enqueue_tree_row(app);
+        },
+        _ => {
+            return false;
+        },
+    }
+
+    return true;
+}
+
+fn play_tree_row(app: &mut App) {
+    let Some(row) = app.tree.selected_row() else {
+        return;
+    };
+
+    let (indices, start) = app.tree.row_tracks(row);
+    let tracks: Vec<Track> = indices
+        .iter()
+        .map(|&i| app.library[i].clone())
+        .collect();
+
+    if tracks.is_empty() {
+        return;
+    }
+
+    start_tracks(app, tracks, start);
+}
+
+fn enqueue_tree_row(app: &mut App) {
+    let Some(row) = app.tree.selected_row() else {
+        return;
+    };
+
+    let (indices, start) = app.tree.row_tracks(row);
+
+    // A single track is enqueued alone, not with the rest of its album
+    let indices = match row {
+        TreeRow::Track(..) => vec![indices[start]],
+        _ => indices,
+    };
+
+    for &i in &indices {
+        app.queue.push_back(app.library[i].clone());
+    }
+
+    show_message(app, format!("Added {} track(s) to queue, {} queued", indices.len(), app.queue.len()));
+}
+
+fn toggle_directories(app: &mut App) {
+    let view = match app.view {
+        View::Directories => View::Playlist,
+        _ => View::Directories,
+    };
+
+    set_view(app, view);
+
+    // The folders may have changed since the last visit
+    if app.view == View::Directories {
+        app.directories.refresh();
+    }
+}
+
+fn handle_directory_key(app: &mut App, key_code: KeyCode) -> bool {
+    if app.view != View::Directories || app.focus != Focus::Tracks {
+        return false;
+    }
+
+    match key_code {
+        _ if key_code == parse_key(&app.config.previous_track) => {
+            app.directories.move_selection(-1);
+        },
+        _ if key_code == parse_key(&app.config.next_track) => {
+            app.directories.move_selection(1);
+        },
+        _ if key_code == parse_key(&app.config.page_up) => {
+            app.directories.move_selection(-page_size(app));
+        },
+        _ if key_code == parse_key(&app.config.page_down) => {
+            app.directories.move_selection(page_size(app));
+        },
+        _ if key_code == parse_key(&app.config.jump_to_top) => {
+            app.directories.move_selection(i32::MIN / 2);
+        },
+        _ if key_code == parse_key(&app.config.jump_to_bottom) => {
+            app.directories.move_selection(i32::MAX / 2);
+        },
+        _ if key_code == parse_key(&app.config.browser_expand) => {
+            app.directories.open();
+        },
+        _ if key_code == parse_key(&app.config.browser_collapse) => {
+            app.directories.parent();
+        },
+        _ if key_code == parse_key(&app.config.select) => {
+            if !app.directories.open() {
+                play_directory_file(app);
+            }
+        },
+        _ if key_code == parse_key(&app.config.play_directory) => {
+            play_directory(app, false);
+        },
+        _ if key_code == parse_key(&app.config.play_directory_recursive) => {
+            play_directory(app, true);
+        },
+        _ => {
+            return false;
+        },
+    }
+
+    return true;
+}
+
+fn play_directory(app: &mut App, recursive: bool) {
+    let Some(directory) = app.directories.target_directory() else {
+        return;
+    };
+
+    let sort_order = app
+        .config
+        .sort_modes
+        .get(app.sort_mode)
+        .cloned()
+        .unwrap_or_else(|| "title".to_string());
+
+    match scan_music_files(std::slice::from_ref(&directory), recursive, &sort_order, app.sort_descending) {
+        Ok(tracks) if !tracks.is_empty() => {
+            let count = tracks.len();
+
+            start_tracks(app, tracks, 0);
+            show_message(app, format!("Playing {} track(s) from {}", count, directory.display()));
+        },
+        Ok(_) => {
+            show_message(app, format!("No music files in {}", directory.display()));
+        },
+        Err(e) => {
+            show_message(app, format!("Error: {}", e));
+        },
+    }
+}
+
+/// Plays the selected file along with the rest of its folder, or the selected playlist
+fn play_directory_file(app: &mut App) {
+    let Some(entry) = app.directories.selected() else {
+        return;
+    };
+
+    let path = entry.path.clone();
+
+    if is_playlist_file(&path) {
+        match load_playlist(&path, &app.library) {
+            Ok(tracks) if !tracks.is_empty() => {
+                start_tracks(app, tracks, 0);
+            },
+            Ok(_) => {
+                show_message(app, format!("No playable tracks in {}", path.display()));
+            },
+            Err(e) => {
+                show_message(app, format!("Error: {}", e));
+            },
+        }
+
+        return;
+    }
+
+    let Some(directory) = app.directories.current.clone() else {
+        return;
+    };
+
+    let sort_order = app
+        .config
+        .sort_modes
+        .get(app.sort_mode)
+        .cloned()
+        .unwrap_or_else(|| "title".to_string());
+
+    let Ok(tracks) = scan_music_files(&[directory], false, &sort_order, app.sort_descending) else {
+        return;
+    };
+
+    // A cue sheet starts from its first track
+    let start = tracks
+        .iter()
+        .position(|t| t.path == path)
+        .or_else(|| {
+            let cue_stem = path.file_stem()?;
+
+            return tracks
+                .iter()
+                .position(|t| t.path.file_stem() == Some(cue_stem));
+        })
+        .unwrap_or(0);
+
+    if !tracks.is_empty() {
+        start_tracks(app, tracks, start);
+    }
+}
+
+fn start_tracks(app: &mut App, tracks: Vec<Track>, start: usize) {
+    app.playback_state = PlaybackState::Stopped;
+
+    set_tracks(app, tracks);
+
+    app.current_track = start;
+    app.list_state.select(Some(start));
+    app.scroll_state = ScrollbarState::new(app.tracks.len()).position(start);
+
+    play_track(app);
+}
+
+fn track_matches(track: &Track, query: &str) -> bool {
+    let query = query.to_lowercase();
+    let metadata = &track.metadata;
+    let title = metadata
+        .title
+        .clone()
+        .or_else(|| {
+            track
+                .path
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string())
+        });
+
+    return [&title, &metadata.artist, &metadata.album]
+        .iter()
+        .any(|field| {
+            field
+                .as_ref()
+                .is_some_and(|v| v.to_lowercase().contains(&query))
+        });
+}
+
+/// Tracks shown in the list: hidden ones only when showing them, and only search matches
+fn visible_tracks(app: &App) -> Vec<usize> {
+    return (0..app.tracks.len())
+        .filter(|&i| app.show_hidden || !app.hidden.contains(&track_key(&app.tracks[i])))
+        .filter(|&i| app.search.is_empty() || track_matches(&app.tracks[i], &app.search))
+        .collect();
+}
+
+/// Hidden tracks are never played on their own, even when shown
+fn is_playable(app: &App, index: usize) -> bool {
+    let track = &app.tracks[index];
+
+    return !app.hidden.contains(&track_key(track))
+        && (app.search.is_empty() || track_matches(track, &app.search));
+}
+
+/// Splits the text into spans with every occurrence of the query reversed
+fn highlight_matches(text: &str, query: &str) -> Line<'static> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let chars: Vec<char> = text.chars().collect();
+    let needle: Vec<char> = query.chars().map(fold).collect();
+
+    if needle.is_empty() {
+        return Line::from(text.to_string());
+    }
+
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let matched = chars.len() - i >= needle.len()
+            && chars[i..i + needle.len()]
+                .iter()
+                .zip(&needle)
+                .all(|(&c, &n)| fold(c) == n);
+
+        if matched {
+            if !plain.is_empty() {
+                spans.push(Span::raw(std::mem::take(&mut plain)));
+            }
+
+            spans.push(Span::styled(
+                chars[i..i + needle.len()].iter().collect::<String>(),
+                Style::default().add_modifier(Modifier::REVERSED),
+            ));
+
+            i += needle.len();
+        } else {
+            plain.push(chars[i]);
+
+            i += 1;
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(Span::raw(plain));
+    }
+
+    return Line::from(spans);
+}
+
+fn handle_finder_key(app: &mut App, key_code: KeyCode) {
+    let Some(finder) = &mut app.finder else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Esc => {
+            app.finder = None;
+        },
+        KeyCode::Up => {
+            finder.move_selection(-1);
+        },
+        KeyCode::Down => {
+            finder.move_selection(1);
+        },
+        KeyCode::PageUp => {
+            finder.move_selection(-10);
+        },
+        KeyCode::PageDown => {
+            finder.move_selection(10);
+        },
+        KeyCode::Backspace => {
+            finder.query.pop();
+            finder.update();
+        },
+        KeyCode::Char(c) => {
+            finder.query.push(c);
+            finder.update();
+        },
+        KeyCode::Enter => {
+            if let Some(index) = finder.selected() {
+                app.finder = None;
+
+                play_library_track(app, index);
+            }
+        },
+        // Enqueues and keeps the finder open to pick more
+        KeyCode::Tab => {
+            if let Some(index) = finder.selected() {
+                let track = app.library[index].clone();
+                let title = track
+                    .metadata
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| "Unknown".to_string());
+
+                app.queue.push_back(track);
+
+                show_message(app, format!("Added \"{}\" to queue, {} queued", title, app.queue.len()));
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Jumps to the track in the playlist, or plays it right after the current one if it isn't there
+fn play_library_track(app: &mut App, index: usize) {
+    let track = app.library[index].clone();
+
+    remember_position(app);
+
+    let position = app
+        .tracks
+        .iter()
+        .position(|t| t.path == track.path && t.start == track.start);
+
+    let target = match position {
+        Some(position) => position,
+        None => {
+            let insert_at = (app.current_track + 1).min(app.tracks.len());
+
+            app.tracks.insert(insert_at, track);
+
+            insert_at
+        },
+    };
+
+    app.current_track = leave_queued_track(app, target);
+
+    // The picked track must be visible
+    if !app.search.is_empty() && !track_matches(&app.tracks[app.current_track], &app.search) {
+        app.search.clear();
+    }
+
+    app.list_state.select(Some(app.current_track));
+    app.scroll_state = ScrollbarState::new(app.tracks.len()).position(app.current_track);
+    app.position = Duration::ZERO;
+
+    play_track(app);
+}
+
+fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    let position = Position::new(mouse.column, mouse.row);
+    let list_area = app.list_area.get();
+    let progress_area = app.progress_area.get();
+
+    // Inside the borders
+    let list_inner = Rect {
+        x: list_area.x + 1,
+        y: list_area.y + 1,
+        width: list_area.width.saturating_sub(2),
+        height: list_area.height.saturating_sub(2),
+    };
+
+    match mouse.kind {
+        MouseEventKind::ScrollUp if list_area.contains(position) => {
+            move_view_selection(app, -1);
+        },
+        MouseEventKind::ScrollDown if list_area.contains(position) => {
+            move_view_selection(app, 1);
+        },
+        MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left)
+            if progress_area.contains(position) || app.drag_position.is_some() =>
+        {
+            // Only seek on release, reopening the file on every drag step is slow
+            let inner_width = progress_area.width.saturating_sub(2).max(1);
+            let ratio = mouse.column.saturating_sub(progress_area.x + 1).min(inner_width) as f64 / inner_width as f64;
+
+            app.drag_position = Some(app.tracks[app.current_track].duration.mul_f64(ratio));
+        },
+        MouseEventKind::Up(MouseButton::Left) => {
+            if let Some(position) = app.drag_position.take() {
+                seek_to(app, position);
+            }
+        },
+        // The scrollbar sits on the last column inside the border
+        MouseEventKind::Down(MouseButton::Left)
+            if app.config.show_playlist_scrollbar
+                && mouse.column == list_inner.right().saturating_sub(1)
+                && list_inner.contains(position) =>
+        {
+            let ratio = (mouse.row - list_inner.y) as f64 / list_inner.height.saturating_sub(1).max(1) as f64;
+            let len = match app.view {
+                View::Playlist => visible_tracks(app).len(),
+                View::Browser => app.tree.rows().len(),
+                View::Directories => app.directories.entries.len(),
+            };
+            let target = (ratio * len.saturating_sub(1) as f64).round() as i32;
+            let selected = match app.view {
+                View::Playlist => {
+                    let cursor = app.list_state.selected().unwrap_or(0);
+
+                    visible_tracks(app)
+                        .iter()
+                        .position(|&i| i == cursor)
+                        .unwrap_or(0)
+                },
+                View::Browser => app.tree.state.selected().unwrap_or(0),
+                View::Directories => app.directories.state.selected().unwrap_or(0),
+            };
+
+            move_view_selection(app, target - selected as i32);
+        },
+        MouseEventKind::Down(MouseButton::Left) if list_inner.contains(position) => {
+            app.focus = Focus::Tracks;
+
+            click_row(app, app.list_offset.get() + (mouse.row - list_inner.y) as usize);
+        },
+        _ => {},
+    }
+}
+
+fn move_view_selection(app: &mut App, offset: i32) {
+    match app.view {
+        View::Playlist => {
+            move_cursor(app, offset);
+        },
+        View::Browser => {
+            app.tree.move_selection(offset);
+        },
+        View::Directories => {
+            app.directories.move_selection(offset);
+        },
+    }
+}
+
+/// The first click on a row selects it, a click on the selected row acts like Enter
+fn click_row(app: &mut App, row: usize) {
+    match app.view {
+        View::Playlist => {
+            let Some(&index) = visible_tracks(app).get(row) else {
+                return;
+            };
+
+            if app.list_state.selected() == Some(index) {
+                play_selected(app);
+            } else {
+                app.list_state.select(Some(index));
+            }
+        },
+        View::Browser => {
+            let Some(&tree_row) = app.tree.rows().get(row) else {
+                return;
+            };
+
+            if app.tree.state.selected() != Some(row) {
+                app.tree.state.select(Some(row));
+            } else if matches!(tree_row, TreeRow::Track(..)) {
+                play_tree_row(app);
+            } else if app.tree.is_expanded(tree_row) {
+                app.tree.collapse();
+            } else {
+                app.tree.expand();
+            }
+        },
+        View::Directories => {
+            if row >= app.directories.entries.len() {
+                return;
+            }
+
+            if app.directories.state.selected() != Some(row) {
+                app.directories.state.select(Some(row));
+            } else if !app.directories.open() {
+                play_directory_file(app);
+            }
+        },
+    }
+}
+
+/// Moves the file the confirmation named to the trash and drops every track of it
+fn trash_track(app: &mut App, confirmed: String) {
+    let Some(path) = app
+        .tracks
+        .iter()
+        .map(|t| &t.path)
+        .find(|p| p.to_string_lossy() == confirmed)
+        .cloned()
+    else {
+        return;
+    };
+
+    let playing = app.tracks[app.current_track].path == path;
+
+    if app.tracks.iter().all(|t| t.path == path) {
+        show_message(app, "Can't trash the only file in the playlist".to_string());
+
+        return;
+    }
+
+    let file = match trash_file(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            show_message(app, format!("Failed to move to trash: {}", e));
+
+            return;
+        },
+    };
+
+    let tracks = remove_file_tracks(&mut app.tracks, &path);
+    let library = remove_file_tracks(&mut app.library, &path);
+
+    app.queue.retain(|t| t.path != path);
+    app.tree = LibraryTree::new(&app.library);
+
+    // The cursor stays on its track, or moves to the one taking the trashed track's place
+    let cursor = app.list_state.selected().unwrap_or(app.current_track);
+    let cursor = cursor - tracks
+        .iter()
+        .filter(|(i, _)| *i < cursor)
+        .count();
+
+    app.list_state.select(Some(cursor.min(app.tracks.len() - 1)));
+
+    if playing {
+        // Carry on with whatever took its place
+        if let Some(sink) = app.sink.take() {
+            sink.stop();
+        }
+
+        app.playing_queued = false;
+        app.current_track = tracks[0].0.min(app.tracks.len() - 1);
+        app.position = Duration::ZERO;
+
+        next_track(app, 0);
+    } else {
+        app.current_track -= tracks
+            .iter()
+            .filter(|(i, _)| *i < app.current_track)
+            .count();
+    }
+
+    move_cursor(app, 0);
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    show_message(app, format!("Moved \"{}\" to trash, press {} to undo", name, app.config.undo));
+
+    app.undo_stack.push(Undo::Trash {
+        file,
+        tracks,
+        library,
+    });
+}
+
+fn undo_trash(app: &mut App, file: TrashedFile, tracks: Vec<(usize, Track)>, library: Vec<(usize, Track)>) {
+    if let Err(e) = restore_file(&file) {
+        show_message(app, format!("Failed to restore {}: {}", file.original.display(), e));
+
+        return;
+    }
+
+    let cursor = tracks.first().map(|(i, _)| *i);
+
+    // Ascending order, so every index is valid once the earlier ones are back
+    for (i, track) in tracks {
+        let i = i.min(app.tracks.len());
+
+        if i <= app.current_track {
+            app.current_track += 1;
+        }
+
+        app.tracks.insert(i, track);
+    }
+
+    for (i, track) in library {
+        let i = i.min(app.library.len());
+
+        app.library.insert(i, track);
+    }
+
+    app.tree = LibraryTree::new(&app.library);
+
+    if let Some(cursor) = cursor {
+        app.list_state.select(Some(cursor.min(app.tracks.len() - 1)));
+    }
+
+    show_message(app, format!("Restored {}", file.original.display()));
+}
+
+/// Removes every track of the file, returning them with their former indices
+fn remove_file_tracks(tracks: &mut Vec<Track>, path: &Path) -> Vec<(usize, Track)> {
+    let mut removed = Vec::new();
+    let mut index = 0;
+
+    tracks.retain(|track| {
+        let keep = track.path != path;
+
+        if !keep {
+            removed.push((index, track.clone()));
+        }
+
+        index += 1;
+
+        return keep;
+    });
+
+    return removed;
+}
+
+fn toggle_mark(app: &mut App) {
+    let Some(track) = app.list_state.selected().and_then(|i| app.tracks.get(i)) else {
+        return;
+    };
+
+    let key = track_key(track);
+
+    if !app.marked.remove(&key) {
+        app.marked.insert(key);
+    }
+
+    move_cursor(app, 1);
+}
+
+/// The marked tracks, or the selected one if none are marked, without cue sheet tracks
+fn edit_targets(app: &mut App) -> Option<Vec<usize>> {
+    let mut targets: Vec<usize> = (0..app.tracks.len())
+        .filter(|&i| app.marked.contains(&track_key(&app.tracks[i])))
+        .collect();
+
+    if targets.is_empty() {
+        targets.extend(app.list_state.selected().filter(|&i| i < app.tracks.len()));
+    }
+
+    // Cue sheet tracks share one file and its tags
+    let count = targets.len();
+
+    targets.retain(|&i| app.tracks[i].start.is_zero() && app.tracks[i].end.is_none());
+
+    if targets.is_empty() {
+        if count > 0 {
+            show_message(app, "Can't edit cue sheet tracks".to_string());
+        }
+
+        return None;
+    }
+
+    return Some(targets);
+}
+
+fn open_tag_editor(app: &mut App) {
+    if let Some(targets) = edit_targets(app) {
+        app.tag_editor = Some(TagEditor::new(&app.tracks, &targets));
+    }
+}
+
+fn handle_tag_editor_key(app: &mut App, key_code: KeyCode) {
+    let Some(editor) = &mut app.tag_editor else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Esc => {
+            app.tag_editor = None;
+        },
+        KeyCode::Up | KeyCode::BackTab => {
+            editor.move_selection(-1);
+        },
+        KeyCode::Down | KeyCode::Tab => {
+            editor.move_selection(1);
+        },
+        KeyCode::Backspace => {
+            editor.pop();
+        },
+        KeyCode::Char(c) => {
+            editor.push(c);
+        },
+        KeyCode::Enter => {
+            if let Some(editor) = app.tag_editor.take() {
+                save_tags(app, editor);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn save_tags(app: &mut App, editor: TagEditor) {
+    let changes = editor.changes();
+
+    if changes.is_empty() {
+        return;
+    }
+
+    let paths = editor.targets.clone();
+    let mut saved = 0;
+
+    for path in &paths {
+        if let Err(e) = write_tags(path, &changes) {
+            show_message(app, format!("{:#}", e));
+
+            // Keep the form open to fix the value
+            if saved == 0 {
+                app.tag_editor = Some(editor);
+
+                return;
+            }
+
+            break;
+        }
+
+        refresh_metadata(app, path);
+
+        saved += 1;
+    }
+
+    app.tree = LibraryTree::new(&app.library);
+    app.marked.clear();
+
+    if saved == paths.len() {
+        show_message(app, format!("Saved tags of {} file(s)", saved));
+    }
+}
+
+/// Re-reads the tags of every copy of the file, the tags panel and browser read from them
+fn refresh_metadata(app: &mut App, path: &Path) {
+    let metadata = Metadata::from_path(path);
+
+    for track in app.tracks.iter_mut().chain(app.library.iter_mut()).chain(app.queue.iter_mut()) {
+        if track.path == path && track.start.is_zero() {
+            track.metadata = metadata.clone();
+        }
+    }
+}
+
+fn open_pattern_preview(app: &mut App, kind: PatternKind) {
+    if let Some(targets) = edit_targets(app) {
+        app.pattern_preview = Some(PatternPreview::new(kind, &app.config.file_pattern, &app.tracks, &targets, app.directories.roots()));
+    }
+}
+
+fn handle_pattern_preview_key(app: &mut App, key_code: KeyCode) {
+    let Some(preview) = &mut app.pattern_preview else {
+        return;
+    };
+
+    match key_code {
+        KeyCode::Esc => {
+            app.pattern_preview = None;
+        },
+        KeyCode::Up => {
+            preview.move_selection(-1);
+        },
+        KeyCode::Down => {
+            preview.move_selection(1);
+        },
+        KeyCode::PageUp => {
+            preview.move_selection(-10);
+        },
+        KeyCode::PageDown => {
+            preview.move_selection(10);
+        },
+        KeyCode::Enter => {
+            if let Some(preview) = app.pattern_preview.take() {
+                apply_pattern(app, preview);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn apply_pattern(app: &mut App, preview: PatternPreview) {
+    let total = preview.applicable();
+    let mut applied = 0;
+    let mut error = None;
+
+    for entry in preview.entries {
+        let result = match entry.change {
+            Ok(PatternChange::Tags(values)) => write_tags(&entry.path, &values).map(|_| {
+                refresh_metadata(app, &entry.path);
+            }),
+            Ok(PatternChange::Rename(new_path)) => {
+                let library_root = library_root(app.directories.roots(), &entry.path).map(|root| root.to_path_buf());
+
+                match library_root {
+                    Some(library_root) => rename_track_file(app, &entry.path, &new_path, &library_root),
+                    None => Err(anyhow::anyhow!("Not in a music directory")),
+                }
+            },
+            Err(_) => {
+                continue;
+            },
+        };
+
+        match result {
+            Ok(()) => {
+                applied += 1;
+            },
+            Err(e) => {
+                error.get_or_insert(format!("{}: {:#}", entry.path.display(), e));
+            },
+        }
+    }
+
+    app.tree = LibraryTree::new(&app.library);
+    app.directories.refresh();
+    app.marked.clear();
+
+    let verb = match preview.kind {
+        PatternKind::TagsFromPath => "Tagged",
+        PatternKind::RenameFromTags => "Renamed",
+    };
+
+    match error {
+        Some(error) => {
+            show_message(app, format!("{} {} of {} file(s), {}", verb, applied, total, error));
+        },
+        None => {
+            show_message(app, format!("{} {} file(s)", verb, applied));
+        },
+    }
+}
+
+/// Moves the file and follows it everywhere it's referenced by path
+fn rename_track_file(app: &mut App, path: &Path, new_path: &Path, library_root: &Path) -> Result<()> {
+    if !new_path.starts_with(library_root) {
+        anyhow::bail!("{} would leave the library", new_path.display());
+    }
+
+    if new_path.exists() {
+        anyhow::bail!("{} already exists", new_path.display());
+    }
+
+    if let Some(parent) = new_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::rename(path, new_path)?;
+
+    for track in app.tracks.iter_mut().chain(app.library.iter_mut()).chain(app.queue.iter_mut()) {
+        if track.path == path {
+            track.path = new_path.to_path_buf();
+        }
+    }
+
+    let old_key = path.to_string_lossy().to_string();
+    let new_key = new_path.to_string_lossy().to_string();
+
+    if app.hidden.remove(&old_key) {
+        app.hidden.insert(new_key.clone());
+        save_hidden(app);
+    }
+
+    if let Some(position) = app.resume_positions.remove(&old_key) {
+        app.resume_positions.insert(new_key, position);
+        save_resume_positions(app);
+    }
+
+    // Clean up the directories the pattern moved the file out of if that left them empty, the library itself stays
+    let mut directory = path.parent();
+
+    while let Some(d) = directory {
+        if d == library_root || !d.starts_with(library_root) || std::fs::remove_dir(d).is_err() {
+            break;
+        }
+
+        directory = d.parent();
+    }
+
+    return Ok(());
 }
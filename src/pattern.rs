@@ -0,0 +1,529 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use anyhow::Result;
+use ratatui::widgets::ListState;
+
+use crate::{
+    tags::TagField,
+    Metadata,
+    Track,
+};
+
+/// What `Metadata::from_path` guesses from file names without tags
+pub const NAME_PATTERN: &str = "%artist% - %title%";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PatternKind {
+    TagsFromPath,
+    RenameFromTags,
+}
+
+pub enum PatternChange {
+    Tags(Vec<(TagField, String)>),
+    Rename(PathBuf),
+}
+
+pub struct PreviewEntry {
+    pub path: PathBuf,
+    /// Why the track is left alone otherwise
+    pub change: Result<PatternChange, String>,
+}
+
+/// Dry run of a pattern over some tracks, applied only once confirmed
+pub struct PatternPreview {
+    pub kind: PatternKind,
+    pub pattern: String,
+    pub entries: Vec<PreviewEntry>,
+    pub state: ListState,
+}
+
+impl PatternPreview {
+    pub fn new(kind: PatternKind, pattern: &str, tracks: &[Track], targets: &[usize], roots: &[PathBuf]) -> Self {
+        let mut entries: Vec<PreviewEntry> = targets
+            .iter()
+            .map(|&index| {
+                let path = tracks[index].path.clone();
+                let change = match kind {
+                    PatternKind::TagsFromPath => parse_path(pattern, &path)
+                        .map(PatternChange::Tags)
+                        .ok_or_else(|| "Doesn't match the pattern".to_string()),
+                    PatternKind::RenameFromTags => library_root(roots, &path)
+                        .ok_or_else(|| anyhow::anyhow!("Not in a music directory"))
+                        .and_then(|root| format_path(pattern, &path, root, &tracks[index].metadata))
+                        .map_err(|e| e.to_string())
+                        .and_then(|new_path| {
+                            if new_path == path {
+                                return Err("Already named so".to_string());
+                            }
+
+                            if new_path.exists() {
+                                return Err(format!("{} already exists", new_path.display()));
+                            }
+
+                            return Ok(PatternChange::Rename(new_path));
+                        }),
+                };
+
+                return PreviewEntry {
+                    path,
+                    change,
+                };
+            })
+            .collect();
+
+        // Two tracks must not end up in the same file
+        for i in 0..entries.len() {
+            let Ok(PatternChange::Rename(new_path)) = &entries[i].change else {
+                continue;
+            };
+
+            let taken = entries[..i]
+                .iter()
+                .any(|e| matches!(&e.change, Ok(PatternChange::Rename(other)) if other == new_path));
+
+            if taken {
+                entries[i].change = Err(format!("{} is taken by another track", new_path.display()));
+            }
+        }
+
+        return Self {
+            kind,
+            pattern: pattern.to_string(),
+            entries,
+            state: ListState::default().with_selected(Some(0)),
+        };
+    }
+
+    /// Number of tracks that would change
+    pub fn applicable(&self) -> usize {
+        return self
+            .entries
+            .iter()
+            .filter(|e| e.change.is_ok())
+            .count();
+    }
+
+    pub fn move_selection(&mut self, offset: i32) {
+        let len = self.entries.len() as i32;
+
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.state.selected().unwrap_or(0) as i32;
+
+        self.state.select(Some((selected + offset).clamp(0, len - 1) as usize));
+    }
+}
+
+enum Token {
+    Text(String),
+    Field(TagField),
+}
+
+/// Reads tag values from the end of the path, one pattern segment per path component
+///
+/// `%artist%/%album%/%track% - %title%` matches `.../Artist/Album/01 - Title.flac`
+fn parse_path(pattern: &str, path: &Path) -> Option<Vec<(TagField, String)>> {
+    let segments = pattern.split('/').count();
+    let mut components: Vec<String> = path
+        .parent()?
+        .components()
+        .rev()
+        .take(segments - 1)
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+
+    components.reverse();
+    components.push(path.file_stem()?.to_string_lossy().to_string());
+
+    if components.len() != segments {
+        return None;
+    }
+
+    let components: Vec<&str> = components
+        .iter()
+        .map(|c| c.as_str())
+        .collect();
+
+    return parse_components(pattern, &components);
+}
+
+pub fn parse_components(pattern: &str, components: &[&str]) -> Option<Vec<(TagField, String)>> {
+    let mut values = Vec::new();
+
+    for (segment, component) in pattern.split('/').zip(components) {
+        values.extend(parse_segment(&tokenize(segment)?, component)?);
+    }
+
+    return Some(values);
+}
+
+/// The music directory the file was found under, the innermost one if they're nested
+pub fn library_root<'a>(roots: &'a [PathBuf], path: &Path) -> Option<&'a Path> {
+    return roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map(|root| root.as_path());
+}
+
+/// Builds the new path for a track, next to the directories the pattern replaces
+///
+/// Those are never looked for above `library_root`, a flat library gets the directories created in it
+fn format_path(pattern: &str, path: &Path, library_root: &Path, metadata: &Metadata) -> Result<PathBuf> {
+    let segments = pattern.split('/').count();
+    let mut root = path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+
+    if !root.starts_with(library_root) {
+        anyhow::bail!("{} is outside the library", path.display());
+    }
+
+    for _ in 1..segments {
+        if root == library_root {
+            break;
+        }
+
+        root.pop();
+    }
+
+    let mut new_path = root;
+    let last = segments - 1;
+
+    for (i, segment) in pattern.split('/').enumerate() {
+        let mut component = String::new();
+
+        for token in tokenize(segment).ok_or_else(|| anyhow::anyhow!("Invalid pattern \"{}\"", pattern))? {
+            match token {
+                Token::Text(text) => {
+                    component.push_str(&text);
+                },
+                Token::Field(field) => {
+                    let value = field_value(metadata, field)
+                        .ok_or_else(|| anyhow::anyhow!("No {}", field.label().to_lowercase()))?;
+
+                    component.push_str(&sanitize(&value));
+                },
+            }
+        }
+
+        if component.trim().is_empty() {
+            anyhow::bail!("Empty path component");
+        }
+
+        if component == ".." {
+            anyhow::bail!("The pattern can't go up a directory");
+        }
+
+        // Appended rather than set, titles like `Mr. Blue` have dots of their own
+        match path.extension() {
+            Some(extension) if i == last => {
+                new_path.push(format!("{}.{}", component, extension.to_string_lossy()));
+            },
+            _ => {
+                new_path.push(component);
+            },
+        }
+    }
+
+    if !new_path.starts_with(library_root) {
+        anyhow::bail!("{} would leave the library", new_path.display());
+    }
+
+    return Ok(new_path);
+}
+
+fn tokenize(segment: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = segment;
+
+    while let Some(start) = rest.find('%') {
+        if start > 0 {
+            tokens.push(Token::Text(rest[..start].to_string()));
+        }
+
+        let end = rest[start + 1..].find('%')? + start + 1;
+        let field = match rest[start + 1..end].to_lowercase().as_str() {
+            "title" => TagField::Title,
+            "artist" => TagField::Artist,
+            "album" => TagField::Album,
+            "year" => TagField::Year,
+            "genre" => TagField::Genre,
+            "track" => TagField::Track,
+            "disc" => TagField::Disc,
+            _ => return None,
+        };
+
+        tokens.push(Token::Field(field));
+        rest = &rest[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+
+    return Some(tokens);
+}
+
+/// Fields take as little as they can, up to the next literal text
+fn parse_segment(tokens: &[Token], text: &str) -> Option<Vec<(TagField, String)>> {
+    let mut values = Vec::new();
+    let mut rest = text;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Text(literal) => {
+                rest = rest.strip_prefix(literal.as_str())?;
+            },
+            Token::Field(field) => {
+                let value = match tokens.get(i + 1) {
+                    Some(Token::Text(next)) => {
+                        let end = rest.find(next.as_str())?;
+                        let value = &rest[..end];
+
+                        rest = &rest[end..];
+
+                        value
+                    },
+                    // Two fields in a row can't be told apart
+                    Some(Token::Field(_)) => {
+                        return None;
+                    },
+                    None => {
+                        let value = rest;
+
+                        rest = "";
+
+                        value
+                    },
+                };
+
+                let value = value.trim();
+
+                if value.is_empty() {
+                    return None;
+                }
+
+                // Numbers must look like numbers, `01` or `1/12`
+                if matches!(field, TagField::Track | TagField::Disc | TagField::Year)
+                    && !value.chars().all(|c| c.is_ascii_digit() || c == '/')
+                {
+                    return None;
+                }
+
+                let value = match field {
+                    TagField::Track | TagField::Disc if value.starts_with('0') && value.len() > 1 => {
+                        value.trim_start_matches('0')
+                    },
+                    _ => value,
+                };
+
+                values.push((*field, value.to_string()));
+            },
+        }
+    }
+
+    if !rest.is_empty() {
+        return None;
+    }
+
+    return Some(values);
+}
+
+fn field_value(metadata: &Metadata, field: TagField) -> Option<String> {
+    match field {
+        TagField::Title => {
+            return metadata.title.clone();
+        },
+        TagField::Artist => {
+            return metadata.artist.clone();
+        },
+        TagField::Album => {
+            return metadata.album.clone();
+        },
+        TagField::Year => {
+            return metadata.year.clone();
+        },
+        TagField::Genre => {
+            return metadata.genre.clone();
+        },
+        TagField::Track => {
+            return metadata.track_number.map(|n| format!("{:02}", n));
+        },
+        TagField::Disc => {
+            return metadata.disc_number.map(|n| n.to_string());
+        },
+    }
+}
+
+/// Tag values may contain characters that aren't allowed in file names
+fn sanitize(value: &str) -> String {
+    return value
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\0' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .trim()
+        .to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(pattern: &str, path: &str) -> Option<Vec<(&'static str, String)>> {
+        return parse_path(pattern, Path::new(path)).map(|values| {
+            return values
+                .into_iter()
+                .map(|(field, value)| (field.label(), value))
+                .collect();
+        });
+    }
+
+    #[test]
+    fn leading_zeros_are_dropped_from_numbers() {
+        assert_eq!(
+            parse("%track% - %title%", "/music/01 - Intro.flac"),
+            Some(vec![("Track", "1".to_string()), ("Title", "Intro".to_string())])
+        );
+        assert_eq!(
+            parse("%track% - %title%", "/music/0 - Silence.flac"),
+            Some(vec![("Track", "0".to_string()), ("Title", "Silence".to_string())])
+        );
+    }
+
+    #[test]
+    fn track_totals_are_kept() {
+        let values = parse_segment(&tokenize("%track%. %title%").unwrap(), "1/12. Title - With Dash").unwrap();
+
+        assert!(matches!(values[0].0, TagField::Track));
+        assert_eq!(values[0].1, "1/12");
+        assert_eq!(values[1].1, "Title - With Dash");
+    }
+
+    #[test]
+    fn mismatches_are_rejected() {
+        assert_eq!(parse("%track% - %title%", "/music/Intro.flac"), None);
+        assert_eq!(parse("%track% - %title%", "/music/A1 - Intro.flac"), None);
+        assert_eq!(parse("%track% - %title%", "/music/01 - .flac"), None);
+        assert_eq!(parse("%artist%%title%", "/music/Intro.flac"), None);
+        assert_eq!(parse("%artist%/%album%/%title%", "Intro.flac"), None);
+        assert!(tokenize("%track - %title%").is_none());
+        assert!(tokenize("%unknown%").is_none());
+    }
+
+    #[test]
+    fn directories_map_to_segments() {
+        assert_eq!(
+            parse("%artist%/%album%/%track% - %title%", "/music/Band/Record/03 - Song.mp3"),
+            Some(vec![
+                ("Artist", "Band".to_string()),
+                ("Album", "Record".to_string()),
+                ("Track", "3".to_string()),
+                ("Title", "Song".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn formatted_paths_round_trip() {
+        let metadata = Metadata {
+            title: Some("AC/DC Cover".to_string()),
+            artist: Some("Band".to_string()),
+            track_number: Some(7),
+            ..Default::default()
+        };
+        let path = Path::new("/music/Band/old name.flac");
+        let new_path = format_path("%artist%/%track% - %title%", path, Path::new("/music"), &metadata).unwrap();
+
+        assert_eq!(new_path, Path::new("/music/Band/07 - AC_DC Cover.flac"));
+        assert_eq!(
+            parse("%artist%/%track% - %title%", new_path.to_str().unwrap()),
+            Some(vec![
+                ("Artist", "Band".to_string()),
+                ("Track", "7".to_string()),
+                ("Title", "AC_DC Cover".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn dotted_titles_keep_their_dots() {
+        let metadata = Metadata {
+            title: Some("Mr. Blue".to_string()),
+            album: Some("Vol. 2".to_string()),
+            track_number: Some(7),
+            ..Default::default()
+        };
+        let path = Path::new("/music/Old/old name.flac");
+        let new_path = format_path("%album%/%track% - %title%", path, Path::new("/music"), &metadata).unwrap();
+
+        assert_eq!(new_path, Path::new("/music/Vol. 2/07 - Mr. Blue.flac"));
+        assert_eq!(
+            parse("%album%/%track% - %title%", new_path.to_str().unwrap()),
+            Some(vec![
+                ("Album", "Vol. 2".to_string()),
+                ("Track", "7".to_string()),
+                ("Title", "Mr. Blue".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn formatting_needs_every_field() {
+        let metadata = Metadata {
+            title: Some("Song".to_string()),
+            ..Default::default()
+        };
+
+        assert!(format_path("%track% - %title%", Path::new("/music/a.flac"), Path::new("/music"), &metadata).is_err());
+    }
+
+    #[test]
+    fn renames_stay_in_the_library() {
+        let metadata = Metadata {
+            title: Some("Song".to_string()),
+            artist: Some("Band".to_string()),
+            album: Some("Record".to_string()),
+            track_number: Some(1),
+            ..Default::default()
+        };
+        let pattern = "%artist%/%album%/%track% - %title%";
+
+        // A flat library gets the directories created inside it
+        assert_eq!(
+            format_path(pattern, Path::new("/home/user/Music/song.mp3"), Path::new("/home/user/Music"), &metadata).unwrap(),
+            Path::new("/home/user/Music/Band/Record/01 - Song.mp3")
+        );
+        assert_eq!(
+            format_path(pattern, Path::new("/music/Rock/Old/Older/song.mp3"), Path::new("/music"), &metadata).unwrap(),
+            Path::new("/music/Rock/Band/Record/01 - Song.mp3")
+        );
+        assert!(format_path(pattern, Path::new("/elsewhere/song.mp3"), Path::new("/music"), &metadata).is_err());
+
+        let roots = [PathBuf::from("/music"), PathBuf::from("/music/Rock")];
+
+        assert_eq!(library_root(&roots, Path::new("/music/Rock/a.mp3")), Some(Path::new("/music/Rock")));
+        assert_eq!(library_root(&roots, Path::new("/music/a.mp3")), Some(Path::new("/music")));
+        assert_eq!(library_root(&roots, Path::new("/musical/a.mp3")), None);
+    }
+}
@@ -0,0 +1,996 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    fmt::Write,
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+use anyhow::{
+    bail,
+    Result,
+};
+
+use crate::{
+    data_directory,
+    get_audio_duration,
+    is_music_file,
+    Metadata,
+    Track,
+};
+
+#[derive(Default)]
+struct PlaylistEntry {
+    location: String,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    track_number: Option<u32>,
+    duration: Option<Duration>,
+    /// Where a cue sheet track starts and ends in its file
+    start: Duration,
+    end: Option<Duration>,
+}
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    let extensions = [
+        "m3u",
+        "m3u8",
+        "pls",
+        "xspf",
+    ];
+
+    return extensions.contains(&extension(path).as_str());
+}
+
+pub fn load_playlist(path: &Path, library: &[Track]) -> Result<Vec<Track>> {
+    let contents = read_text(path)?;
+    let base_directory = path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_default();
+
+    let entries = match extension(path).as_str() {
+        "pls" => parse_pls(&contents),
+        "xspf" => parse_xspf(&contents),
+        _ => parse_m3u(&contents),
+    };
+
+    let mut tracks = Vec::new();
+
+    for entry in entries {
+        let track_path = resolve_location(&entry.location, &base_directory)
+            .filter(|p| p.is_file() && is_music_file(p));
+
+        let Some(track_path) = track_path else {
+            if let Some(track) = find_in_library(&entry, library) {
+                tracks.push(track.clone());
+            }
+
+            continue;
+        };
+
+        // Cue sheet tracks take their titles from the sheet, which the library has read already
+        if (!entry.start.is_zero() || entry.end.is_some())
+            && let Some(track) = find_cue_track(&track_path, &entry, library)
+        {
+            tracks.push(track.clone());
+
+            continue;
+        }
+
+        let mut metadata = Metadata::from_tags(&track_path);
+
+        if let Some(title) = &entry.title {
+            metadata.fill_from_name(title);
+        }
+
+        metadata.fill_from_name(&file_stem(&track_path));
+
+        if metadata.artist.is_none() {
+            metadata.artist = entry.artist;
+        }
+
+        if metadata.album.is_none() {
+            metadata.album = entry.album;
+        }
+
+        if metadata.track_number.is_none() {
+            metadata.track_number = entry.track_number;
+        }
+
+        let duration = match entry.end {
+            Some(end) => end.saturating_sub(entry.start),
+            None => get_audio_duration(&track_path)
+                .ok()
+                .filter(|d| !d.is_zero())
+                .map(|d| d.saturating_sub(entry.start))
+                .or(entry.duration)
+                .unwrap_or(Duration::ZERO),
+        };
+
+        tracks.push(Track {
+            path: track_path,
+            start: entry.start,
+            end: entry.end,
+            duration,
+            metadata,
+        });
+    }
+
+    return Ok(tracks);
+}
+
+pub fn named_playlists() -> Vec<String> {
+    let Some(directory) = playlists_directory() else {
+        return Vec::new();
+    };
+
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| extension(p) == "m3u8")
+        .map(|p| file_stem(&p))
+        .collect();
+
+    names.sort_by_key(|name| name.to_lowercase());
+
+    return names;
+}
+
+pub fn named_playlist_path(name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid playlist name \"{}\"", name);
+    }
+
+    let Some(directory) = playlists_directory() else {
+        bail!("Data directory is not available");
+    };
+
+    std::fs::create_dir_all(&directory)?;
+
+    return Ok(directory.join(format!("{}.m3u8", name)));
+}
+
+fn playlists_directory() -> Option<PathBuf> {
+    return data_directory().map(|d| d.join("playlists"));
+}
+
+fn find_in_library<'a>(entry: &PlaylistEntry, library: &'a [Track]) -> Option<&'a Track> {
+    let (Some(title), Some(artist)) = (&entry.title, &entry.artist) else {
+        return None;
+    };
+
+    return library
+        .iter()
+        .find(|track| {
+            let same_title = track
+                .metadata
+                .title
+                .as_ref()
+                .is_some_and(|t| t.to_lowercase() == title.to_lowercase());
+            let same_artist = track
+                .metadata
+                .artist
+                .as_ref()
+                .is_some_and(|a| a.to_lowercase() == artist.to_lowercase());
+
+            return same_title && same_artist;
+        });
+}
+
+fn find_cue_track<'a>(path: &Path, entry: &PlaylistEntry, library: &'a [Track]) -> Option<&'a Track> {
+    // Written in seconds, so allow for rounding
+    let close = |a: Duration, b: Duration| a.abs_diff(b) < Duration::from_millis(1);
+    let path = absolute_path(path);
+
+    return library
+        .iter()
+        .filter(|track| close(track.start, entry.start))
+        .find(|track| absolute_path(&track.path) == path);
+}
+
+/// Adds one track to the end of an M3U playlist, leaving the entries already there untouched
+pub fn append_to_playlist(path: &Path, track: &Track) -> Result<()> {
+    if !matches!(extension(path).as_str(), "m3u" | "m3u8") {
+        bail!("Can only add to .m3u playlists");
+    }
+
+    let mut contents = if path.exists() {
+        read_text(path)?
+    } else {
+        String::new()
+    };
+
+    if contents.trim().is_empty() {
+        contents = String::from("#EXTM3U\n");
+    } else if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+
+    write_m3u_entry(&mut contents, track, Path::new(""), false)?;
+    std::fs::write(path, contents)?;
+
+    return Ok(());
+}
+
+pub fn save_playlist(path: &Path, tracks: &[Track], relative_paths: bool) -> Result<()> {
+    let base_directory = absolute_path(
+        path
+            .parent()
+            .unwrap_or(Path::new(""))
+    );
+
+    let contents = match extension(path).as_str() {
+        "m3u" | "m3u8" => write_m3u(tracks, &base_directory, relative_paths)?,
+        "xspf" => write_xspf(tracks, &base_directory, relative_paths)?,
+        other => bail!("Can't save .{} playlists, use .m3u8 or .xspf", other),
+    };
+
+    std::fs::write(path, contents)?;
+
+    return Ok(());
+}
+
+fn write_m3u(tracks: &[Track], base_directory: &Path, relative_paths: bool) -> Result<String> {
+    let mut contents = String::from("#EXTM3U\n");
+
+    for track in tracks {
+        write_m3u_entry(&mut contents, track, base_directory, relative_paths)?;
+    }
+
+    return Ok(contents);
+}
+
+fn write_m3u_entry(contents: &mut String, track: &Track, base_directory: &Path, relative_paths: bool) -> Result<()> {
+    let title = track
+        .metadata
+        .title
+        .clone()
+        .unwrap_or_else(|| file_stem(&track.path));
+    let name = match &track.metadata.artist {
+        Some(artist) => format!("{} - {}", artist, title),
+        None => title,
+    };
+
+    let location = track_location(&track.path, base_directory, relative_paths);
+
+    writeln!(contents, "#EXTINF:{},{}", track.duration.as_secs(), name)?;
+
+    // The options VLC uses, so cue sheet tracks don't come back as the whole file
+    if !track.start.is_zero() {
+        writeln!(contents, "#EXTVLCOPT:start-time={}", track.start.as_secs_f64())?;
+    }
+
+    if let Some(end) = track.end {
+        writeln!(contents, "#EXTVLCOPT:stop-time={}", end.as_secs_f64())?;
+    }
+
+    writeln!(contents, "{}", location.display())?;
+
+    return Ok(());
+}
+
+fn write_xspf(tracks: &[Track], base_directory: &Path, relative_paths: bool) -> Result<String> {
+    let mut contents = String::new();
+
+    writeln!(contents, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(contents, r#"<playlist version="1" xmlns="http://xspf.org/ns/0/" xmlns:vlc="http://www.videolan.org/vlc/playlist/ns/0/">"#)?;
+    writeln!(contents, "  <trackList>")?;
+
+    for track in tracks {
+        let location = track_location(&track.path, base_directory, relative_paths);
+        let location = if location.is_absolute() {
+            format!("file://{}", percent_encode(&location.to_string_lossy()))
+        } else {
+            percent_encode(&location.to_string_lossy())
+        };
+
+        writeln!(contents, "    <track>")?;
+        writeln!(contents, "      <location>{}</location>", xml_escape(&location))?;
+
+        if let Some(title) = &track.metadata.title {
+            writeln!(contents, "      <title>{}</title>", xml_escape(title))?;
+        }
+
+        if let Some(artist) = &track.metadata.artist {
+            writeln!(contents, "      <creator>{}</creator>", xml_escape(artist))?;
+        }
+
+        if let Some(album) = &track.metadata.album {
+            writeln!(contents, "      <album>{}</album>", xml_escape(album))?;
+        }
+
+        if let Some(track_number) = track.metadata.track_number {
+            writeln!(contents, "      <trackNum>{}</trackNum>", track_number)?;
+        }
+
+        if !track.duration.is_zero() {
+            writeln!(contents, "      <duration>{}</duration>", track.duration.as_millis())?;
+        }
+
+        if !track.start.is_zero() || track.end.is_some() {
+            writeln!(contents, r#"      <extension application="http://www.videolan.org/vlc/playlist/0">"#)?;
+
+            if !track.start.is_zero() {
+                writeln!(contents, "        <vlc:option>start-time={}</vlc:option>", track.start.as_secs_f64())?;
+            }
+
+            if let Some(end) = track.end {
+                writeln!(contents, "        <vlc:option>stop-time={}</vlc:option>", end.as_secs_f64())?;
+            }
+
+            writeln!(contents, "      </extension>")?;
+        }
+
+        writeln!(contents, "    </track>")?;
+    }
+
+    writeln!(contents, "  </trackList>")?;
+    writeln!(contents, "</playlist>")?;
+
+    return Ok(contents);
+}
+
+fn track_location(path: &Path, base_directory: &Path, relative_paths: bool) -> PathBuf {
+    let track_path = absolute_path(path);
+
+    if relative_paths {
+        return relative_path(&track_path, base_directory);
+    } else {
+        return track_path;
+    }
+}
+
+fn absolute_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    return std::env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+}
+
+fn relative_path(path: &Path, base_directory: &Path) -> PathBuf {
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base_directory.components().collect();
+
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // Nothing in common except the root, an absolute path is clearer
+    if common <= 1 {
+        return path.to_path_buf();
+    }
+
+    let mut relative = PathBuf::new();
+
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+
+    return relative;
+}
+
+pub fn read_text(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)?;
+
+    // `.m3u` files are not guaranteed to be UTF-8, fall back to Latin-1
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            e
+                .into_bytes()
+                .iter()
+                .map(|&b| b as char)
+                .collect()
+        },
+    };
+
+    return Ok(
+        text
+            .trim_start_matches('\u{feff}')
+            .to_string()
+    );
+}
+
+fn parse_m3u(contents: &str) -> Vec<PlaylistEntry> {
+    let mut entries = Vec::new();
+    let mut title = None;
+    let mut duration = None;
+    let mut start = Duration::ZERO;
+    let mut end = None;
+
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            match parse_time_option(option) {
+                Some(("start-time", time)) => {
+                    start = time;
+                },
+                Some(("stop-time", time)) => {
+                    end = Some(time);
+                },
+                _ => {},
+            }
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (length, name) = info
+                .split_once(',')
+                .unwrap_or((info, ""));
+
+            // Attributes like `tvg-id="..."` may follow the length
+            let length = length
+                .split_whitespace()
+                .next()
+                .unwrap_or("");
+
+            duration = length
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| *secs > 0.0)
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+            title = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            entries.push(PlaylistEntry {
+                location: line.to_string(),
+                title: title.take(),
+                duration: duration.take(),
+                start: std::mem::take(&mut start),
+                end: end.take(),
+                ..Default::default()
+            });
+        }
+    }
+
+    return entries;
+}
+
+fn parse_pls(contents: &str) -> Vec<PlaylistEntry> {
+    let mut entries: Vec<(u32, PlaylistEntry)> = Vec::new();
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+
+        let (field, number) = if let Some(n) = key.strip_prefix("file") {
+            ("file", n)
+        } else if let Some(n) = key.strip_prefix("title") {
+            ("title", n)
+        } else if let Some(n) = key.strip_prefix("length") {
+            ("length", n)
+        } else {
+            continue;
+        };
+
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+
+        let index = match entries.iter().position(|(n, _)| *n == number) {
+            Some(index) => index,
+            None => {
+                entries.push((number, PlaylistEntry::default()));
+
+                entries.len() - 1
+            },
+        };
+
+        let entry = &mut entries[index].1;
+
+        match field {
+            "file" => {
+                entry.location = value.to_string();
+            },
+            "title" => {
+                entry.title = Some(value.to_string()).filter(|t| !t.is_empty());
+            },
+            _ => {
+                entry.duration = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| *secs > 0.0)
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).ok());
+            },
+        }
+    }
+
+    entries.sort_by_key(|(n, _)| *n);
+
+    return entries
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .filter(|entry| !entry.location.is_empty())
+        .collect();
+}
+
+fn parse_xspf(contents: &str) -> Vec<PlaylistEntry> {
+    let Some(track_list) = xml_elements(contents, "trackList").into_iter().next() else {
+        return Vec::new();
+    };
+
+    let mut entries = Vec::new();
+
+    for track in xml_elements(track_list, "track") {
+        let text = |name: &str| {
+            return xml_elements(track, name)
+                .into_iter()
+                .next()
+                .map(|value| xml_unescape(value.trim()))
+                .filter(|value| !value.is_empty());
+        };
+
+        // Locations are URIs, relative ones are percent-encoded too
+        let location = match text("location") {
+            Some(location) if location.contains("://") => location,
+            Some(location) => percent_decode(&location),
+            None => String::new(),
+        };
+
+        let mut start = Duration::ZERO;
+        let mut end = None;
+
+        for option in xml_elements(track, "vlc:option") {
+            match parse_time_option(&xml_unescape(option)) {
+                Some(("start-time", time)) => {
+                    start = time;
+                },
+                Some(("stop-time", time)) => {
+                    end = Some(time);
+                },
+                _ => {},
+            }
+        }
+
+        entries.push(PlaylistEntry {
+            location,
+            start,
+            end,
+            title: text("title"),
+            artist: text("creator"),
+            album: text("album"),
+            track_number: text("trackNum").and_then(|n| n.parse().ok()),
+            duration: text("duration")
+                .and_then(|ms| ms.parse::<u64>().ok())
+                .map(Duration::from_millis),
+        });
+    }
+
+    return entries;
+}
+
+/// `start-time=12.5`, in seconds
+fn parse_time_option(option: &str) -> Option<(&str, Duration)> {
+    let (name, value) = option.trim().split_once('=')?;
+    let time = Duration::try_from_secs_f64(value.trim().parse().ok()?).ok()?;
+
+    return Some((name.trim(), time));
+}
+
+fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut elements = Vec::new();
+    let open_tag = format!("<{}", name);
+    let close_tag = format!("</{}>", name);
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open_tag) {
+        let after_name = &rest[start + open_tag.len()..];
+
+        // Skip longer names sharing the prefix, e.g. `<trackList>` for `<track>`
+        if !after_name.starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            rest = after_name;
+
+            continue;
+        }
+
+        let Some(tag_end) = after_name.find('>') else {
+            break;
+        };
+
+        if after_name[..tag_end].ends_with('/') {
+            rest = &after_name[tag_end + 1..];
+
+            continue;
+        }
+
+        let body = &after_name[tag_end + 1..];
+
+        let Some(end) = body.find(&close_tag) else {
+            break;
+        };
+
+        elements.push(&body[..end]);
+        rest = &body[end + close_tag.len()..];
+    }
+
+    return elements;
+}
+
+fn xml_unescape(text: &str) -> String {
+    let text = text
+        .trim_start_matches("<![CDATA[")
+        .trim_end_matches("]]>");
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => {
+                u32::from_str_radix(&entity[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+            },
+            _ if entity.starts_with('#') => {
+                entity[1..]
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(char::from_u32)
+            },
+            _ => None,
+        };
+
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    unescaped.push_str(rest);
+
+    return unescaped;
+}
+
+fn xml_escape(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;");
+}
+
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+
+    for byte in input.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    return encoded;
+}
+
+fn resolve_location(location: &str, base_directory: &Path) -> Option<PathBuf> {
+    let path = if let Some(uri) = location.strip_prefix("file://") {
+        PathBuf::from(percent_decode(uri))
+    } else if location.contains("://") {
+        // Streams and other remote entries can't be played
+        return None;
+    } else {
+        PathBuf::from(location)
+    };
+
+    if path.is_absolute() {
+        return Some(path);
+    } else {
+        return Some(base_directory.join(path));
+    }
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let high = (bytes[i + 1] as char).to_digit(16);
+            let low = (bytes[i + 2] as char).to_digit(16);
+
+            if let (Some(high), Some(low)) = (high, low) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+fn extension(path: &Path) -> String {
+    return path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+}
+
+fn file_stem(path: &Path) -> String {
+    return path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Unknown")
+        .to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for every test
+    fn scratch_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("sonido-playlist-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+
+        return directory;
+    }
+
+    fn track(path: &Path, title: &str, start: Duration, end: Option<Duration>) -> Track {
+        return Track {
+            path: path.to_path_buf(),
+            start,
+            end,
+            duration: end.unwrap_or(Duration::from_secs(600)).saturating_sub(start),
+            metadata: Metadata {
+                title: Some(title.to_string()),
+                artist: Some("Band".to_string()),
+                ..Default::default()
+            },
+        };
+    }
+
+    #[test]
+    fn relative_locations_resolve_against_the_playlist() {
+        let base = Path::new("/music/lists");
+
+        assert_eq!(resolve_location("../Band/song.mp3", base), Some(PathBuf::from("/music/lists/../Band/song.mp3")));
+        assert_eq!(resolve_location("/other/song.mp3", base), Some(PathBuf::from("/other/song.mp3")));
+        assert_eq!(resolve_location("http://example.com/stream", base), None);
+
+        assert_eq!(relative_path(Path::new("/music/Band/song.mp3"), base), Path::new("../Band/song.mp3"));
+        assert_eq!(relative_path(Path::new("/music/lists/song.mp3"), base), Path::new("song.mp3"));
+        assert_eq!(relative_path(Path::new("/other/song.mp3"), base), Path::new("/other/song.mp3"));
+    }
+
+    #[test]
+    fn file_uris_are_percent_decoded() {
+        assert_eq!(
+            resolve_location("file:///music/My%20Band/Caf%C3%A9.flac", Path::new("/")),
+            Some(PathBuf::from("/music/My Band/Café.flac"))
+        );
+        assert_eq!(percent_decode("100%25 %zz %2"), "100% %zz %2");
+        assert_eq!(percent_decode(&percent_encode("/a b/Café&.mp3")), "/a b/Café&.mp3");
+    }
+
+    #[test]
+    fn extinf_gives_title_and_duration() {
+        let entries = parse_m3u(concat!(
+            "#EXTM3U\n",
+            "#EXTINF:123 tvg-id=\"x\",Band - Song\n",
+            "song.mp3\n",
+            "\n",
+            "#EXTINF:-1,Radio\n",
+            "http://example.com/stream\n",
+            "#EXTINF:1e300,Forever\n",
+            "# a comment\n",
+            "forever.mp3\n",
+            "untitled.mp3\n",
+        ));
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].location, "song.mp3");
+        assert_eq!(entries[0].title.as_deref(), Some("Band - Song"));
+        assert_eq!(entries[0].duration, Some(Duration::from_secs(123)));
+        assert_eq!(entries[1].duration, None);
+        assert_eq!(entries[2].title.as_deref(), Some("Forever"));
+        assert_eq!(entries[2].duration, None);
+        assert_eq!(entries[3].title, None);
+    }
+
+    #[test]
+    fn pls_keys_pair_up_by_number() {
+        let entries = parse_pls(concat!(
+            "[playlist]\n",
+            "Title2=Second\n",
+            "File2=second.mp3\n",
+            "file1=first.mp3\n",
+            "LENGTH1=61.5\n",
+            "Title1=First\n",
+            "Title3=No file\n",
+            "Length2=-1\n",
+            "NumberOfEntries=3\n",
+        ));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "first.mp3");
+        assert_eq!(entries[0].title.as_deref(), Some("First"));
+        assert_eq!(entries[0].duration, Some(Duration::from_millis(61_500)));
+        assert_eq!(entries[1].location, "second.mp3");
+        assert_eq!(entries[1].title.as_deref(), Some("Second"));
+        assert_eq!(entries[1].duration, None);
+    }
+
+    #[test]
+    fn xspf_round_trips_escaped_text() {
+        let directory = scratch_directory("xspf");
+        let song = directory.join("Tom & Jerry <Live> café.mp3");
+        let mut saved = track(&song, "Tom & Jerry <Live> — Café", Duration::ZERO, None);
+
+        saved.metadata.artist = Some("Björk & \"Friends\"".to_string());
+        saved.metadata.album = Some("Ça <va>".to_string());
+        std::fs::write(&song, b"").unwrap();
+
+        let playlist = directory.join("list.xspf");
+
+        save_playlist(&playlist, std::slice::from_ref(&saved), true).unwrap();
+
+        let entries = parse_xspf(&read_text(&playlist).unwrap());
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, "Tom & Jerry <Live> café.mp3");
+        assert_eq!(entries[0].title.as_deref(), Some("Tom & Jerry <Live> — Café"));
+        assert_eq!(entries[0].artist.as_deref(), Some("Björk & \"Friends\""));
+        assert_eq!(entries[0].album.as_deref(), Some("Ça <va>"));
+        assert_eq!(resolve_location(&entries[0].location, &directory), Some(song.clone()));
+
+        save_playlist(&playlist, std::slice::from_ref(&saved), false).unwrap();
+
+        let entries = parse_xspf(&read_text(&playlist).unwrap());
+
+        assert_eq!(resolve_location(&entries[0].location, Path::new("/")), Some(song.clone()));
+
+        // Once the file is gone, the same artist and title are looked for in the library
+        std::fs::remove_file(&song).unwrap();
+
+        let mut moved = saved.clone();
+
+        moved.path = PathBuf::from("/music/moved.mp3");
+        moved.metadata.title = Some("TOM & JERRY <LIVE> — CAFÉ".to_string());
+
+        let library = [
+            track(Path::new("/music/other.mp3"), "Tom & Jerry <Live> — Café", Duration::ZERO, None),
+            moved,
+        ];
+        let tracks = load_playlist(&playlist, &library).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].path, Path::new("/music/moved.mp3"));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn unsupported_formats_are_not_saved() {
+        let directory = scratch_directory("formats");
+        let playlist = directory.join("list.pls");
+
+        assert!(save_playlist(&playlist, &[], false).is_err());
+        assert!(!playlist.exists());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn cue_offsets_are_kept() {
+        let start = Duration::from_secs(305) + Duration::from_nanos(13_333_333);
+        let end = Some(Duration::from_secs(1200));
+        let cue_track = track(Path::new("/music/album.flac"), "Middle", start, end);
+
+        let entries = parse_m3u(&write_m3u(std::slice::from_ref(&cue_track), Path::new("/"), false).unwrap());
+
+        assert_eq!(entries[0].location, "/music/album.flac");
+        assert!(entries[0].start.abs_diff(start) < Duration::from_millis(1));
+        assert_eq!(entries[0].end, end);
+
+        let entries = parse_xspf(&write_xspf(&[cue_track], Path::new("/"), false).unwrap());
+
+        assert!(entries[0].start.abs_diff(start) < Duration::from_millis(1));
+        assert_eq!(entries[0].end, end);
+    }
+
+    #[test]
+    fn cue_tracks_reload_from_the_library() {
+        let directory = scratch_directory("cue");
+        let album = directory.join("album.flac");
+
+        std::fs::write(&album, b"").unwrap();
+
+        let library = [
+            track(&album, "First", Duration::ZERO, Some(Duration::from_secs(90))),
+            track(&album, "Second", Duration::from_secs(90), None),
+        ];
+        let playlist = directory.join("list.m3u8");
+
+        save_playlist(&playlist, &library[1..], true).unwrap();
+
+        let tracks = load_playlist(&playlist, &library).unwrap();
+
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].metadata.title.as_deref(), Some("Second"));
+        assert_eq!(tracks[0].start, Duration::from_secs(90));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn appending_keeps_missing_entries() {
+        let directory = scratch_directory("append");
+        let playlist = directory.join("list.m3u8");
+        let song = directory.join("song.mp3");
+
+        std::fs::write(&playlist, "#EXTM3U\n#EXTINF:10,Gone - Missing\n/nowhere/missing.mp3").unwrap();
+        append_to_playlist(&playlist, &track(&song, "Song", Duration::ZERO, None)).unwrap();
+
+        let entries = parse_m3u(&read_text(&playlist).unwrap());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "/nowhere/missing.mp3");
+        assert_eq!(entries[1].location, song.to_string_lossy());
+        assert_eq!(entries[1].title.as_deref(), Some("Band - Song"));
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,310 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+use anyhow::{
+    Context,
+    Result,
+};
+use lofty::{
+    read_from_path,
+    config::WriteOptions,
+    file::TaggedFileExt,
+    tag::{
+        Accessor,
+        Tag,
+        TagExt,
+    },
+};
+
+use crate::Track;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Genre,
+    Track,
+    Disc,
+}
+
+impl TagField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TagField::Title => {
+                return "Title";
+            },
+            TagField::Artist => {
+                return "Artist";
+            },
+            TagField::Album => {
+                return "Album";
+            },
+            TagField::Year => {
+                return "Year";
+            },
+            TagField::Genre => {
+                return "Genre";
+            },
+            TagField::Track => {
+                return "Track";
+            },
+            TagField::Disc => {
+                return "Disc";
+            },
+        }
+    }
+}
+
+pub struct EditorField {
+    pub field: TagField,
+    pub value: String,
+    /// The tracks being edited disagree, and the field hasn't been touched
+    pub mixed: bool,
+    pub changed: bool,
+}
+
+/// Tag editing form for one or more tracks
+pub struct TagEditor {
+    /// Files being edited, kept as paths since the playlist may change while the form is open
+    pub targets: Vec<PathBuf>,
+    pub fields: Vec<EditorField>,
+    pub selected: usize,
+}
+
+impl TagEditor {
+    pub fn new(tracks: &[Track], targets: &[usize]) -> Self {
+        let fields = [
+            TagField::Title,
+            TagField::Artist,
+            TagField::Album,
+            TagField::Year,
+            TagField::Genre,
+            TagField::Track,
+            TagField::Disc,
+        ]
+            .into_iter()
+            .map(|field| {
+                let mut values = targets
+                    .iter()
+                    .map(|&i| field_value(&tracks[i], field));
+
+                let first = values.next().unwrap_or_default();
+                let mixed = values.any(|v| v != first);
+
+                return EditorField {
+                    field,
+                    value: if mixed {
+                        String::new()
+                    } else {
+                        first
+                    },
+                    mixed,
+                    changed: false,
+                };
+            })
+            .collect();
+
+        let mut targets: Vec<PathBuf> = targets
+            .iter()
+            .map(|&i| tracks[i].path.clone())
+            .collect();
+
+        targets.dedup();
+
+        return Self {
+            targets,
+            fields,
+            selected: 0,
+        };
+    }
+
+    pub fn move_selection(&mut self, offset: i32) {
+        let len = self.fields.len() as i32;
+
+        self.selected = (self.selected as i32 + offset).rem_euclid(len) as usize;
+    }
+
+    pub fn push(&mut self, c: char) {
+        let field = &mut self.fields[self.selected];
+
+        field.value.push(c);
+        field.mixed = false;
+        field.changed = true;
+    }
+
+    /// On a mixed field this clears it for every track
+    pub fn pop(&mut self) {
+        let field = &mut self.fields[self.selected];
+
+        field.value.pop();
+        field.mixed = false;
+        field.changed = true;
+    }
+
+    pub fn changes(&self) -> Vec<(TagField, String)> {
+        return self
+            .fields
+            .iter()
+            .filter(|f| f.changed)
+            .map(|f| (f.field, f.value.trim().to_string()))
+            .collect();
+    }
+}
+
+fn field_value(track: &Track, field: TagField) -> String {
+    let metadata = &track.metadata;
+    let numbered = |number: Option<u32>, total: Option<u32>| {
+        match (number, total) {
+            (Some(number), Some(total)) => format!("{}/{}", number, total),
+            (Some(number), None) => number.to_string(),
+            _ => String::new(),
+        }
+    };
+
+    match field {
+        TagField::Title => {
+            return metadata.title.clone().unwrap_or_default();
+        },
+        TagField::Artist => {
+            return metadata.artist.clone().unwrap_or_default();
+        },
+        TagField::Album => {
+            return metadata.album.clone().unwrap_or_default();
+        },
+        TagField::Year => {
+            return metadata.year.clone().unwrap_or_default();
+        },
+        TagField::Genre => {
+            return metadata.genre.clone().unwrap_or_default();
+        },
+        TagField::Track => {
+            return numbered(metadata.track_number, metadata.track_total);
+        },
+        TagField::Disc => {
+            return numbered(metadata.disc_number, metadata.disc_total);
+        },
+    }
+}
+
+/// Writes the changed fields to the file's primary tag, creating it if needed; empty values remove the field
+pub fn write_tags(path: &Path, changes: &[(TagField, String)]) -> Result<()> {
+    let mut tagged_file = read_from_path(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+
+    let tag = tagged_file
+        .primary_tag_mut()
+        .context("Tags not supported")?;
+
+    for (field, value) in changes {
+        let value = value.as_str();
+
+        match field {
+            TagField::Title => {
+                if value.is_empty() {
+                    tag.remove_title();
+                } else {
+                    tag.set_title(value.to_string());
+                }
+            },
+            TagField::Artist => {
+                if value.is_empty() {
+                    tag.remove_artist();
+                } else {
+                    tag.set_artist(value.to_string());
+                }
+            },
+            TagField::Album => {
+                if value.is_empty() {
+                    tag.remove_album();
+                } else {
+                    tag.set_album(value.to_string());
+                }
+            },
+            TagField::Genre => {
+                if value.is_empty() {
+                    tag.remove_genre();
+                } else {
+                    tag.set_genre(value.to_string());
+                }
+            },
+            TagField::Year => {
+                if value.is_empty() {
+                    tag.remove_year();
+                } else {
+                    tag.set_year(parse_number(value, "year")?);
+                }
+            },
+            TagField::Track => {
+                let (number, total) = parse_numbered(value)?;
+
+                match number {
+                    Some(number) => tag.set_track(number),
+                    None => tag.remove_track(),
+                }
+
+                match total {
+                    Some(total) => tag.set_track_total(total),
+                    None => tag.remove_track_total(),
+                }
+            },
+            TagField::Disc => {
+                let (number, total) = parse_numbered(value)?;
+
+                match number {
+                    Some(number) => tag.set_disk(number),
+                    None => tag.remove_disk(),
+                }
+
+                match total {
+                    Some(total) => tag.set_disk_total(total),
+                    None => tag.remove_disk_total(),
+                }
+            },
+        }
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    return Ok(());
+}
+
+fn parse_number(value: &str, name: &str) -> Result<u32> {
+    return value
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid {} \"{}\"", name, value));
+}
+
+/// `3` or `3/12`
+fn parse_numbered(value: &str) -> Result<(Option<u32>, Option<u32>)> {
+    if value.is_empty() {
+        return Ok((None, None));
+    }
+
+    match value.split_once('/') {
+        Some((number, total)) => {
+            return Ok((Some(parse_number(number, "number")?), Some(parse_number(total, "total")?)));
+        },
+        None => {
+            return Ok((Some(parse_number(value, "number")?), None));
+        },
+    }
+}
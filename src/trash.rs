@@ -0,0 +1,202 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    fs::{
+        self,
+        OpenOptions,
+    },
+    io::{
+        ErrorKind,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+use anyhow::{
+    Context,
+    Result,
+};
+use directories::BaseDirs;
+
+use crate::playlist::percent_encode;
+
+/// A file moved to the trash, enough to put it back
+pub struct TrashedFile {
+    pub original: PathBuf,
+    trashed: PathBuf,
+    info: PathBuf,
+}
+
+/// Moves the file to the home trash, following the freedesktop.org Trash specification
+pub fn trash_file(path: &Path) -> Result<TrashedFile> {
+    let original = path
+        .canonicalize()
+        .with_context(|| format!("{} not found", path.display()))?;
+    let trash_directory = home_trash().context("No home directory")?;
+    let files_directory = trash_directory.join("files");
+    let info_directory = trash_directory.join("info");
+
+    fs::create_dir_all(&files_directory)?;
+    fs::create_dir_all(&info_directory)?;
+
+    let file_name = original
+        .file_name()
+        .context("Not a file")?
+        .to_string_lossy()
+        .to_string();
+
+    // Creating the info file first reserves the name, as the spec requires
+    let mut counter = 1;
+
+    let (name, mut info_file) = loop {
+        let name = if counter == 1 {
+            file_name.clone()
+        } else {
+            match file_name.rsplit_once('.') {
+                Some((stem, extension)) => format!("{}.{}.{}", stem, counter, extension),
+                None => format!("{}.{}", file_name, counter),
+            }
+        };
+
+        let info_path = info_directory.join(format!("{}.trashinfo", name));
+
+        match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(file) => {
+                break (name, file);
+            },
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                counter += 1;
+            },
+            Err(e) => {
+                return Err(e.into());
+            },
+        }
+    };
+
+    let info = info_directory.join(format!("{}.trashinfo", name));
+    let trashed = files_directory.join(&name);
+
+    let result = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&original.to_string_lossy()),
+        deletion_date(),
+    )
+        .map_err(anyhow::Error::from)
+        .and_then(|_| move_file(&original, &trashed));
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&info);
+
+        return Err(e);
+    }
+
+    return Ok(TrashedFile {
+        original,
+        trashed,
+        info,
+    });
+}
+
+/// Moves the file back where it was and drops its trash info
+pub fn restore_file(file: &TrashedFile) -> Result<()> {
+    if file.original.exists() {
+        anyhow::bail!("{} already exists", file.original.display());
+    }
+
+    if let Some(parent) = file.original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    move_file(&file.trashed, &file.original)?;
+
+    let _ = fs::remove_file(&file.info);
+
+    return Ok(());
+}
+
+fn home_trash() -> Option<PathBuf> {
+    // `data_dir` already honours `$XDG_DATA_HOME`
+    return BaseDirs::new().map(|d| d.data_dir().join("Trash"));
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to) {
+        Ok(()) => {
+            return Ok(());
+        },
+        // Another file system, e.g. music on an external drive
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)?;
+
+            return Ok(());
+        },
+        Err(e) => {
+            return Err(e.into());
+        },
+    }
+}
+
+/// `YYYY-MM-DDThh:mm:ss` in local time, with no zone as the spec asks
+fn deletion_date() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let seconds = now + utc_offset(now);
+
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+
+    // Days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+    );
+}
+
+/// Seconds the local time zone is ahead of UTC at `time`, from the C library's time zone rules
+fn utc_offset(time: i64) -> i64 {
+    let time = time as libc::time_t;
+
+    // SAFETY: `localtime_r` only writes to the `tm` it's given, which lives on the stack
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+        return 0;
+    }
+
+    return local.tm_gmtoff as i64;
+}
@@ -0,0 +1,640 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+use ratatui::prelude::*;
+use rodio::{
+    source::SeekError,
+    Source,
+};
+
+/// Samples per FFT, about 46 ms at 44.1 kHz
+const FFT_SIZE: usize = 2048;
+
+/// Interleaved samples kept for the visualizers
+const TAP_CAPACITY: usize = FFT_SIZE * 8;
+
+/// Samples collected before taking the lock, so the audio thread rarely waits on it
+const TAP_BATCH: usize = 512;
+
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+
+/// Quietest level shown, in dB below full scale
+const FLOOR_DB: f32 = 60.0;
+
+/// Frames the meters measure, about 46 ms at 44.1 kHz like the spectrum
+const METER_FRAMES: usize = 2048;
+
+/// Frames across the oscilloscope, about 23 ms at 44.1 kHz
+const SCOPE_FRAMES: usize = 1024;
+
+pub const BAR_SYMBOLS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+
+/// Eighths of a cell filled from the left, for the meters
+const METER_SYMBOLS: [&str; 9] = [" ", "▏", "▎", "▍", "▌", "▋", "▊", "▉", "█"];
+
+/// Braille dots by row for the left and right column of a cell
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VisualizerMode {
+    Spectrum,
+    Meters,
+    Scope,
+}
+
+impl VisualizerMode {
+    /// Unknown names show the spectrum
+    pub fn parse(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "meters" | "vu" => {
+                return VisualizerMode::Meters;
+            },
+            "scope" | "oscilloscope" => {
+                return VisualizerMode::Scope;
+            },
+            _ => {
+                return VisualizerMode::Spectrum;
+            },
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            VisualizerMode::Spectrum => {
+                return VisualizerMode::Meters;
+            },
+            VisualizerMode::Meters => {
+                return VisualizerMode::Scope;
+            },
+            VisualizerMode::Scope => {
+                return VisualizerMode::Spectrum;
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct TapBuffer {
+    samples: VecDeque<i16>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+/// Copy of the latest samples sent to the sink, shared with the audio thread
+#[derive(Clone, Default)]
+pub struct SampleTap {
+    buffer: Arc<Mutex<TapBuffer>>,
+}
+
+impl SampleTap {
+    pub fn wrap<S: Source<Item = i16>>(&self, source: S) -> Tap<S> {
+        self.clear();
+
+        return Tap {
+            source,
+            buffer: self.buffer.clone(),
+            pending: Vec::with_capacity(TAP_BATCH),
+        };
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.samples.clear();
+        }
+    }
+
+    /// The last `frames` frames mixed down to mono, and the sample rate
+    pub fn mono(&self, frames: usize) -> (Vec<f32>, u32) {
+        let Ok(buffer) = self.buffer.lock() else {
+            return (Vec::new(), 0);
+        };
+
+        let channels = buffer.channels.max(1) as usize;
+        let available = buffer.samples.len() / channels * channels;
+        let start = available.saturating_sub(frames * channels);
+
+        let mono = buffer
+            .samples
+            .range(start..available)
+            .collect::<Vec<_>>()
+            .chunks(channels)
+            .map(|frame| frame.iter().map(|&&s| s as f32).sum::<f32>() / (channels as f32 * i16::MAX as f32))
+            .collect();
+
+        return (mono, buffer.sample_rate);
+    }
+
+    /// The last `frames` frames, one list of samples between -1 and 1 per channel
+    pub fn channels(&self, frames: usize) -> Vec<Vec<f32>> {
+        let Ok(buffer) = self.buffer.lock() else {
+            return Vec::new();
+        };
+
+        let channels = buffer.channels as usize;
+
+        if channels == 0 {
+            return Vec::new();
+        }
+
+        let available = buffer.samples.len() / channels * channels;
+        let start = available.saturating_sub(frames * channels);
+        let mut split = vec![Vec::with_capacity((available - start) / channels); channels];
+
+        for (i, &sample) in buffer.samples.range(start..available).enumerate() {
+            split[i % channels].push(sample as f32 / i16::MAX as f32);
+        }
+
+        return split;
+    }
+}
+
+/// Passes the samples through untouched, copying them to the tap on the way
+pub struct Tap<S> {
+    source: S,
+    buffer: Arc<Mutex<TapBuffer>>,
+    pending: Vec<i16>,
+}
+
+impl<S: Source<Item = i16>> Tap<S> {
+    fn flush(&mut self) {
+        let Ok(mut buffer) = self.buffer.lock() else {
+            return;
+        };
+
+        let channels = self.source.channels().max(1);
+
+        // Samples of another layout can't be split into the new channels
+        if buffer.channels != channels {
+            buffer.samples.clear();
+        }
+
+        buffer.channels = channels;
+        buffer.sample_rate = self.source.sample_rate();
+        buffer.samples.extend(self.pending.drain(..));
+
+        // Trimmed by whole frames, so the buffer always starts with the first channel
+        let length = buffer.samples.len();
+        let excess = length
+            .saturating_sub(TAP_CAPACITY)
+            .next_multiple_of(channels as usize)
+            .min(length);
+
+        buffer.samples.drain(..excess);
+    }
+}
+
+impl<S: Source<Item = i16>> Iterator for Tap<S> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+
+        self.pending.push(sample);
+
+        // Only whole frames are handed over
+        if self.pending.len() >= TAP_BATCH && self.pending.len().is_multiple_of(self.source.channels().max(1) as usize) {
+            self.flush();
+        }
+
+        return Some(sample);
+    }
+}
+
+impl<S: Source<Item = i16>> Source for Tap<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        return self.source.current_frame_len();
+    }
+
+    fn channels(&self) -> u16 {
+        return self.source.channels();
+    }
+
+    fn sample_rate(&self) -> u32 {
+        return self.source.sample_rate();
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        return self.source.total_duration();
+    }
+
+    fn try_seek(&mut self, position: Duration) -> Result<(), SeekError> {
+        self.pending.clear();
+
+        return self.source.try_seek(position);
+    }
+}
+
+/// Bar heights between 0 and 1 that rise at once and fall slowly
+pub struct Spectrum {
+    pub bars: Vec<f64>,
+    updated: Instant,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        return Self {
+            bars: Vec::new(),
+            updated: Instant::now(),
+        };
+    }
+}
+
+impl Spectrum {
+    /// `falloff` is how far bars may drop per second, in panel heights; silence when not playing
+    pub fn update(&mut self, tap: &SampleTap, playing: bool, bands: usize, falloff: f64) {
+        let elapsed = self.updated.elapsed().as_secs_f64();
+        let levels = if playing {
+            let (samples, sample_rate) = tap.mono(FFT_SIZE);
+
+            band_levels(&samples, sample_rate, bands)
+        } else {
+            vec![0.0; bands]
+        };
+
+        self.bars.resize(bands, 0.0);
+
+        for (bar, level) in self.bars.iter_mut().zip(levels) {
+            *bar = level.max(*bar - falloff * elapsed).max(0.0);
+        }
+
+        self.updated = Instant::now();
+    }
+
+    /// Bars spread over the area, one column gap between them when there's room
+    pub fn lines(&self, area: Rect, color: Color) -> Vec<Line<'static>> {
+        let count = self.bars.len();
+
+        if count == 0 || area.width == 0 {
+            return Vec::new();
+        }
+
+        let width = area.width as usize;
+        let slot = (width / count).max(1);
+        let gap = (slot >= 3) as usize;
+        let height = area.height as usize;
+
+        // Eighths of a cell filled in every column
+        let columns: Vec<usize> = (0..width)
+            .map(|x| {
+                let bar = x / slot;
+
+                if bar >= count || x % slot >= slot - gap {
+                    return 0;
+                }
+
+                return (self.bars[bar] * (height * 8) as f64).round() as usize;
+            })
+            .collect();
+
+        return (0..height)
+            .map(|row| {
+                // Rows count from the bottom
+                let floor = (height - 1 - row) * 8;
+                let text: String = columns
+                    .iter()
+                    .map(|&filled| BAR_SYMBOLS[filled.saturating_sub(floor).min(8)])
+                    .collect();
+
+                return Line::styled(text, Style::default().fg(color));
+            })
+            .collect();
+    }
+}
+
+/// Peak and RMS level of every channel between 0 and 1, falling slowly like the spectrum bars
+pub struct Meters {
+    pub peaks: Vec<f64>,
+    pub rms: Vec<f64>,
+    updated: Instant,
+}
+
+impl Default for Meters {
+    fn default() -> Self {
+        return Self {
+            peaks: Vec::new(),
+            rms: Vec::new(),
+            updated: Instant::now(),
+        };
+    }
+}
+
+impl Meters {
+    pub fn update(&mut self, tap: &SampleTap, playing: bool, falloff: f64) {
+        let elapsed = self.updated.elapsed().as_secs_f64();
+        let levels: Vec<(f64, f64)> = if playing {
+            tap.channels(METER_FRAMES)
+                .iter()
+                .map(|samples| {
+                    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+                    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt();
+
+                    return (level(peak), level(rms));
+                })
+                .collect()
+        } else {
+            vec![(0.0, 0.0); self.peaks.len()]
+        };
+
+        self.peaks.resize(levels.len(), 0.0);
+        self.rms.resize(levels.len(), 0.0);
+
+        for (i, (peak, rms)) in levels.into_iter().enumerate() {
+            self.peaks[i] = peak.max(self.peaks[i] - falloff * elapsed).max(0.0);
+            self.rms[i] = rms.max(self.rms[i] - falloff * elapsed).max(0.0);
+        }
+
+        self.updated = Instant::now();
+    }
+
+    /// How much louder the left channel is than the right, in dB, for stereo only
+    pub fn balance(&self) -> Option<f64> {
+        if self.rms.len() != 2 || self.rms.contains(&0.0) {
+            return None;
+        }
+
+        return Some((self.rms[0] - self.rms[1]) * FLOOR_DB as f64);
+    }
+
+    /// A row per channel, RMS as the bar and peak as a mark past it, the louder channel's alone when rows run short
+    pub fn lines(&self, area: Rect, color: Color) -> Vec<Line<'static>> {
+        let width = area.width as usize;
+        let height = area.height as usize;
+
+        if self.peaks.is_empty() || width == 0 || height == 0 {
+            return Vec::new();
+        }
+
+        let max = |levels: &[f64]| levels.iter().fold(0.0f64, |max, &l| max.max(l));
+        let meters: Vec<(&str, f64, f64)> = if height < self.peaks.len() {
+            vec![("M", max(&self.peaks), max(&self.rms))]
+        } else {
+            let labels: &[&str] = match self.peaks.len() {
+                1 => &["M"],
+                2 => &["L", "R"],
+                _ => &["1", "2", "3", "4", "5", "6", "7", "8"],
+            };
+
+            self.peaks
+                .iter()
+                .zip(&self.rms)
+                .enumerate()
+                .map(|(i, (&peak, &rms))| (labels.get(i).copied().unwrap_or("·"), peak, rms))
+                .collect()
+        };
+
+        // Labels and numbers go first when the panel is narrow
+        let show_label = width >= 12;
+        let show_number = width >= 24;
+        let bar_width = width - show_label as usize * 2 - show_number as usize * 6;
+
+        let rows = height / meters.len();
+        let gap = (rows >= 3) as usize;
+        let mut lines = vec![Line::default(); (height - rows * meters.len()) / 2];
+
+        for (label, peak, rms) in meters {
+            let filled = (rms * (bar_width * 8) as f64).round() as usize;
+            let peak_column = ((peak * bar_width as f64).ceil() as usize).min(bar_width);
+            let bar: String = (0..bar_width)
+                .map(|x| {
+                    if x * 8 >= filled && x + 1 == peak_column {
+                        return "│";
+                    }
+
+                    return METER_SYMBOLS[filled.saturating_sub(x * 8).min(8)];
+                })
+                .collect();
+
+            let mut spans = Vec::new();
+
+            if show_label {
+                spans.push(Span::styled(format!("{} ", label), Style::default().fg(color)));
+            }
+
+            spans.push(Span::styled(bar, Style::default().fg(color)));
+
+            if show_number {
+                // Red once the peak reaches full scale
+                let number_color = if peak >= 1.0 {
+                    Color::Red
+                } else {
+                    color
+                };
+
+                spans.push(Span::styled(format!("{:>6}", decibels(peak)), Style::default().fg(number_color)));
+            }
+
+            for _ in 0..rows - gap {
+                lines.push(Line::from(spans.clone()));
+            }
+
+            lines.extend(std::iter::repeat_n(Line::default(), gap));
+        }
+
+        return lines;
+    }
+}
+
+/// Waveform of the last few milliseconds in braille dots, a lane per channel when there are two rows for each
+pub fn scope_lines(tap: &SampleTap, playing: bool, area: Rect, color: Color) -> Vec<Line<'static>> {
+    let width = area.width as usize;
+    let height = area.height as usize;
+
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let mut channels = if playing {
+        tap.channels(SCOPE_FRAMES * 2)
+    } else {
+        Vec::new()
+    };
+
+    if channels.is_empty() || channels[0].len() < SCOPE_FRAMES {
+        // A flat line when there's nothing to show
+        channels = vec![vec![0.0; SCOPE_FRAMES]];
+    }
+
+    let frames = channels[0].len();
+    let mono: Vec<f32> = (0..frames)
+        .map(|i| channels.iter().map(|c| c[i]).sum::<f32>() / channels.len() as f32)
+        .collect();
+
+    // Start on a rising zero crossing, so a steady tone stands still
+    let start = (1..=frames - SCOPE_FRAMES)
+        .find(|&i| mono[i - 1] < 0.0 && mono[i] >= 0.0)
+        .unwrap_or(frames - SCOPE_FRAMES);
+
+    let lanes = if height >= channels.len() * 2 {
+        channels
+    } else {
+        vec![mono]
+    };
+
+    let columns = width * 2;
+    let rows = height * 4;
+    let lane_height = rows / lanes.len();
+    let mut cells = vec![0u8; width * height];
+
+    for (lane, samples) in lanes.iter().enumerate() {
+        let top = lane * lane_height;
+        let mut previous: Option<usize> = None;
+
+        for x in 0..columns {
+            let sample = samples[start + x * SCOPE_FRAMES / columns].clamp(-1.0, 1.0);
+            let y = top + ((1.0 - sample) / 2.0 * (lane_height - 1) as f32).round() as usize;
+
+            // Joined to the previous dot, so steep edges stay visible
+            let (from, to) = match previous {
+                Some(previous) => (previous.min(y), previous.max(y)),
+                None => (y, y),
+            };
+
+            for dot in from..=to {
+                cells[dot / 4 * width + x / 2] |= BRAILLE_DOTS[dot % 4][x % 2];
+            }
+
+            previous = Some(y);
+        }
+    }
+
+    return cells
+        .chunks(width)
+        .map(|row| {
+            let text: String = row
+                .iter()
+                .map(|&dots| char::from_u32(0x2800 + dots as u32).unwrap_or(' '))
+                .collect();
+
+            return Line::styled(text, Style::default().fg(color));
+        })
+        .collect();
+}
+
+/// Loudness of log-spaced frequency bands, 0 at the floor and 1 at full scale
+fn band_levels(samples: &[f32], sample_rate: u32, bands: usize) -> Vec<f64> {
+    if samples.len() < FFT_SIZE || sample_rate == 0 || bands == 0 {
+        return vec![0.0; bands];
+    }
+
+    // Hann window against leakage between bins
+    let mut real: Vec<f32> = samples[samples.len() - FFT_SIZE..]
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / (FFT_SIZE - 1) as f32).cos()))
+        .collect();
+    let mut imaginary = vec![0.0; FFT_SIZE];
+
+    fft(&mut real, &mut imaginary);
+
+    // The window halves the amplitude and half the energy is in the mirrored bins
+    let magnitudes: Vec<f32> = (0..FFT_SIZE / 2)
+        .map(|k| (real[k] * real[k] + imaginary[k] * imaginary[k]).sqrt() * 4.0 / FFT_SIZE as f32)
+        .collect();
+
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let max_frequency = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+    let ratio = max_frequency / MIN_FREQUENCY;
+
+    return (0..bands)
+        .map(|band| {
+            let low = MIN_FREQUENCY * ratio.powf(band as f32 / bands as f32);
+            let high = MIN_FREQUENCY * ratio.powf((band + 1) as f32 / bands as f32);
+            let first = (low / bin_width).round() as usize;
+            let last = ((high / bin_width).round() as usize).max(first + 1).min(magnitudes.len());
+
+            let peak = magnitudes[first.min(last - 1)..last]
+                .iter()
+                .fold(0.0f32, |peak, &m| peak.max(m));
+
+            return level(peak);
+        })
+        .collect();
+}
+
+/// Amplitude in dB, 0 at the floor and 1 at full scale
+fn level(amplitude: f32) -> f64 {
+    let db = 20.0 * amplitude.max(1e-9).log10();
+
+    return ((db + FLOOR_DB) / FLOOR_DB).clamp(0.0, 1.0) as f64;
+}
+
+/// Level from `level` back in dB, for the numbers next to the meters
+fn decibels(level: f64) -> String {
+    if level <= 0.0 {
+        return "-inf".to_string();
+    }
+
+    return format!("{:.1}", (level - 1.0) * FLOOR_DB as f64);
+}
+
+/// In-place iterative radix-2 FFT, the length must be a power of two
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let n = real.len();
+    let mut j = 0;
+
+    // Bit-reversal permutation
+    for i in 1..n {
+        let mut bit = n >> 1;
+
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+
+        j |= bit;
+
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        let (step_imaginary, step_real) = angle.sin_cos();
+
+        for start in (0..n).step_by(length) {
+            let (mut w_real, mut w_imaginary) = (1.0f32, 0.0f32);
+
+            for k in 0..length / 2 {
+                let a = start + k;
+                let b = a + length / 2;
+
+                let t_real = real[b] * w_real - imaginary[b] * w_imaginary;
+                let t_imaginary = real[b] * w_imaginary + imaginary[b] * w_real;
+
+                real[b] = real[a] - t_real;
+                imaginary[b] = imaginary[a] - t_imaginary;
+                real[a] += t_real;
+                imaginary[a] += t_imaginary;
+
+                (w_real, w_imaginary) = (
+                    w_real * step_real - w_imaginary * step_imaginary,
+                    w_real * step_imaginary + w_imaginary * step_real,
+                );
+            }
+        }
+
+        length <<= 1;
+    }
+}
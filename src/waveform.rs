@@ -0,0 +1,272 @@
+/*
+Copyright (C) 2025 Desyatkov Sergey
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version
+*/
+
+use std::{
+    collections::HashMap,
+    hash::{
+        DefaultHasher,
+        Hash,
+        Hasher,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        mpsc::{
+            self,
+            Receiver,
+            Sender,
+        },
+        Arc,
+    },
+    thread,
+    time::UNIX_EPOCH,
+};
+use directories::ProjectDirs;
+use ratatui::prelude::*;
+use rodio::{
+    Decoder,
+    Source,
+};
+
+use crate::{
+    visualizer::BAR_SYMBOLS,
+    Track,
+};
+
+/// Peaks per track, whatever its length
+const BUCKETS: usize = 1024;
+
+/// Samples decoded between checks for a newer request
+const CANCEL_CHECK: usize = 65536;
+
+/// Peak overviews of tracks, analyzed one at a time in the background and cached on disk
+pub struct Waveforms {
+    cache: HashMap<String, Arc<Vec<u8>>>,
+    /// Key of the track asked for last
+    current: Option<String>,
+    /// Bumped with every request, the worker drops work once it's out of date
+    generation: Arc<AtomicU64>,
+    requests: Sender<(u64, String, Track)>,
+    results: Receiver<(String, Option<Vec<u8>>)>,
+}
+
+impl Waveforms {
+    pub fn new() -> Self {
+        let (requests, request_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+        let worker_generation = generation.clone();
+
+        thread::spawn(move || worker(request_receiver, result_sender, worker_generation));
+
+        return Self {
+            cache: HashMap::new(),
+            current: None,
+            generation,
+            requests,
+            results,
+        };
+    }
+
+    /// Makes the track the current one, analyzing it unless it's known already
+    pub fn request(&mut self, track: &Track) {
+        let key = cache_key(track);
+
+        // Whatever the worker is busy with isn't wanted anymore
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if !self.cache.contains_key(&key) {
+            let _ = self.requests.send((generation, key.clone(), track.clone()));
+        }
+
+        self.current = Some(key);
+    }
+
+    /// Peaks of the current track, once they're ready
+    pub fn current(&mut self) -> Option<Arc<Vec<u8>>> {
+        while let Ok((key, peaks)) = self.results.try_recv() {
+            // Files that can't be decoded aren't tried again
+            self.cache.insert(key, Arc::new(peaks.unwrap_or_default()));
+        }
+
+        return self
+            .cache
+            .get(self.current.as_ref()?)
+            .filter(|peaks| !peaks.is_empty())
+            .cloned();
+    }
+}
+
+/// Takes the newest request whenever it's free, the ones skipped meanwhile are stale
+fn worker(requests: Receiver<(u64, String, Track)>, results: Sender<(String, Option<Vec<u8>>)>, generation: Arc<AtomicU64>) {
+    while let Ok(mut request) = requests.recv() {
+        while let Ok(newer) = requests.try_recv() {
+            request = newer;
+        }
+
+        let (request_generation, key, track) = request;
+        let stale = || generation.load(Ordering::Relaxed) != request_generation;
+
+        let peaks = match read_cached(&key) {
+            Some(peaks) => Some(peaks),
+            None => match analyze(&track, &stale) {
+                Analysis::Done(peaks) => {
+                    write_cached(&key, &peaks);
+
+                    Some(peaks)
+                },
+                Analysis::Failed => None,
+                Analysis::Cancelled => continue,
+            },
+        };
+
+        if results.send((key, peaks)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Bars rising from the bottom, the played part in `played_color`
+pub fn waveform_lines(peaks: &[u8], area: Rect, progress: f64, played_color: Color, remaining_color: Color) -> Vec<Line<'static>> {
+    let width = area.width as usize;
+    let height = area.height as usize;
+
+    if peaks.is_empty() || width == 0 {
+        return Vec::new();
+    }
+
+    let played = (progress.clamp(0.0, 1.0) * width as f64).round() as usize;
+
+    // Eighths of a cell filled in every column, from the loudest peak under it
+    let columns: Vec<usize> = (0..width)
+        .map(|x| {
+            let first = x * peaks.len() / width;
+            let last = ((x + 1) * peaks.len() / width).max(first + 1);
+            let peak = peaks[first..last.min(peaks.len())]
+                .iter()
+                .max()
+                .copied()
+                .unwrap_or(0);
+
+            // Keep silence visible as a thin line
+            return (peak as usize * height * 8 / 255).max(1);
+        })
+        .collect();
+
+    return (0..height)
+        .map(|row| {
+            let floor = (height - 1 - row) * 8;
+            let spans: Vec<Span> = columns
+                .iter()
+                .enumerate()
+                .map(|(x, &filled)| {
+                    let color = if x < played {
+                        played_color
+                    } else {
+                        remaining_color
+                    };
+
+                    return Span::styled(BAR_SYMBOLS[filled.saturating_sub(floor).min(8)], Style::default().fg(color));
+                })
+                .collect();
+
+            return Line::from(spans);
+        })
+        .collect();
+}
+
+/// Changes when the file does, so edited files are analyzed again
+fn cache_key(track: &Track) -> String {
+    let modified = std::fs::metadata(&track.path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut hasher = DefaultHasher::new();
+
+    track.path.hash(&mut hasher);
+    track.start.hash(&mut hasher);
+    track.end.hash(&mut hasher);
+    modified.hash(&mut hasher);
+
+    return format!("{:016x}", hasher.finish());
+}
+
+fn cache_path(key: &str) -> Option<PathBuf> {
+    return ProjectDirs::from("", "", "sonido").map(|d| d.cache_dir().join("waveforms").join(key));
+}
+
+fn read_cached(key: &str) -> Option<Vec<u8>> {
+    return std::fs::read(cache_path(key)?)
+        .ok()
+        .filter(|peaks| peaks.len() == BUCKETS);
+}
+
+fn write_cached(key: &str, peaks: &[u8]) {
+    if let Some(path) = cache_path(key) {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        let _ = std::fs::write(path, peaks);
+    }
+}
+
+enum Analysis {
+    Done(Vec<u8>),
+    Failed,
+    /// Another track was asked for meanwhile
+    Cancelled,
+}
+
+/// Decodes the whole track once, keeping the loudest sample of every bucket
+fn analyze(track: &Track, stale: &dyn Fn() -> bool) -> Analysis {
+    let Some(mut decoder) = std::fs::File::open(&track.path)
+        .ok()
+        .and_then(|file| Decoder::new(std::io::BufReader::new(file)).ok())
+    else {
+        return Analysis::Failed;
+    };
+
+    let channels = decoder.channels().max(1) as f64;
+    let sample_rate = decoder.sample_rate() as f64;
+    let total = (track.duration.as_secs_f64() * sample_rate * channels).max(1.0);
+
+    let source: Box<dyn Iterator<Item = i16>> = if track.start.is_zero() || decoder.try_seek(track.start).is_ok() {
+        Box::new(decoder)
+    } else {
+        Box::new(decoder.skip_duration(track.start))
+    };
+
+    let mut peaks = vec![0u16; BUCKETS];
+
+    for (i, sample) in source.take(total as usize).enumerate() {
+        if i % CANCEL_CHECK == 0 && stale() {
+            return Analysis::Cancelled;
+        }
+
+        let bucket = ((i as f64 / total * BUCKETS as f64) as usize).min(BUCKETS - 1);
+
+        peaks[bucket] = peaks[bucket].max(sample.unsigned_abs());
+    }
+
+    // Scaled to the loudest peak, so quiet recordings still show their shape
+    let loudest = peaks.iter().max().copied().unwrap_or(0).max(1) as u32;
+
+    return Analysis::Done(
+        peaks
+            .iter()
+            .map(|&peak| (peak as u32 * 255 / loudest) as u8)
+            .collect()
+    );
+}